@@ -0,0 +1,122 @@
+//! Portable JSON backup of everything stored in the database.
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::projects::Project;
+use crate::tasks::{CurrentTask, Task};
+
+/// Version of the backup document, increased when its format changes so
+/// older versions of timekeep don't misread newer backups.
+pub const BACKUP_VERSION: u32 = 1;
+
+/// All tasks, projects and the current task, independent of the database schema.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u32,
+    pub tasks: Vec<BackupTask>,
+    #[serde(default)]
+    pub projects: Vec<Project>,
+    #[serde(default)]
+    pub current_task: Option<CurrentTask>,
+}
+
+/// Stored task, including its id, with the key of the import record it came
+/// from so the record isn't imported again after restoring.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupTask {
+    #[serde(flatten)]
+    pub task: Task,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub import_key: Option<String>,
+}
+
+impl Backup {
+    pub fn new(
+        tasks: Vec<BackupTask>,
+        projects: Vec<Project>,
+        current_task: Option<CurrentTask>,
+    ) -> Backup {
+        Backup {
+            version: BACKUP_VERSION,
+            tasks,
+            projects,
+            current_task,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Read backup from JSON, fails if it was created by a newer version of timekeep.
+    pub fn from_json(json: &str) -> Result<Backup> {
+        let backup: Backup = serde_json::from_str(json)?;
+        if backup.version > BACKUP_VERSION {
+            return Err(anyhow!(
+                "backup version {} was created by a newer version of timekeep (expected {} or older)",
+                backup.version,
+                BACKUP_VERSION
+            ));
+        }
+
+        Ok(backup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{Backup, BackupTask, BACKUP_VERSION};
+    use crate::projects::Project;
+    use crate::tasks::{CurrentTask, Task};
+
+    #[test]
+    fn test_backup_json() {
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2022, 7, 1, 10, 0, 0).unwrap();
+        let mut project = Project::new("acme".to_owned());
+        project.set_rate(Some(50.0));
+        project.set_tag_rate("urgent", Some(75.0));
+        let backup = Backup::new(
+            vec![
+                BackupTask {
+                    task: Task::new("acme".to_owned(), start, end, None)
+                        .with_tags(vec!["urgent".to_owned()])
+                        .with_id(Some(7)),
+                    import_key: Some("toggl:123".to_owned()),
+                },
+                BackupTask {
+                    task: Task::new("acme".to_owned(), end, end, None).with_id(Some(9)),
+                    import_key: None,
+                },
+            ],
+            vec![project.clone()],
+            Some(CurrentTask::new(
+                "other".to_owned(),
+                end,
+                Some("notes".to_owned()),
+            )),
+        );
+
+        let restored = Backup::from_json(&backup.to_json().unwrap()).unwrap();
+        assert_eq!(restored.version, BACKUP_VERSION);
+        let tasks: Vec<(Option<i64>, Option<&str>)> = restored
+            .tasks
+            .iter()
+            .map(|t| (t.task.id(), t.import_key.as_deref()))
+            .collect();
+        assert_eq!(tasks, vec![(Some(7), Some("toggl:123")), (Some(9), None)]);
+        assert_eq!(restored.tasks[0].task.tags(), ["urgent"]);
+        assert_eq!(restored.tasks[0].task.end_time(), &end);
+        assert_eq!(restored.projects, vec![project]);
+        let current = restored.current_task.unwrap();
+        assert_eq!(current.project_name(), "other");
+        assert_eq!(current.description(), Some("notes"));
+
+        let newer = format!(r#"{{"version": {}, "tasks": []}}"#, BACKUP_VERSION + 1);
+        assert!(Backup::from_json(&newer).is_err());
+        let minimal = r#"{"version": 1, "tasks": []}"#;
+        assert!(Backup::from_json(minimal).unwrap().current_task.is_none());
+    }
+}
@@ -0,0 +1,256 @@
+//! Charts drawn with Unicode block characters, for showing in the terminal.
+use std::collections::BTreeMap;
+
+use chrono::{Duration, NaiveDate, NaiveTime, TimeZone, Timelike, Utc};
+
+use crate::duration::DurationFormat;
+use crate::table::{project_colour, Style};
+use crate::tasks::Task;
+
+/// Partial blocks for the end of a bar, in eighths of a character.
+const BAR_EIGHTHS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+const FULL_BLOCK: char = '█';
+const SPARK_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Heatmap shades for an empty hour and up to each quarter of an hour tracked.
+const SHADES: [char; 5] = ['·', '░', '▒', '▓', '█'];
+/// Bar widths used when the terminal width isn't known, or is very narrow / wide.
+const DEFAULT_BAR_WIDTH: usize = 40;
+const MIN_BAR_WIDTH: usize = 10;
+const MAX_BAR_WIDTH: usize = 60;
+
+/// Horizontal bar `fraction` of `width` characters long, using partial blocks
+/// for the end of the bar.
+fn bar(fraction: f64, width: usize) -> String {
+    let eighths = (fraction.clamp(0.0, 1.0) * (width * 8) as f64).round() as usize;
+
+    let mut bar: String = std::iter::repeat_n(FULL_BLOCK, eighths / 8).collect();
+    let partial = eighths % 8;
+    if partial > 0 {
+        bar.push(BAR_EIGHTHS[partial - 1]);
+    }
+    bar
+}
+
+/// Fraction of `max` which `d` is, 0 if `max` is zero.
+fn fraction(d: Duration, max: Duration) -> f64 {
+    if max <= Duration::zero() {
+        return 0.0;
+    }
+    d.num_milliseconds() as f64 / max.num_milliseconds() as f64
+}
+
+/// Horizontal bar chart of the duration for each project, scaled so the
+/// longest fills the available width.
+///
+/// Labels can be indented project names, bars are coloured with the project's colour.
+pub fn bar_chart(rows: &[(String, Duration)], format: DurationFormat, style: &Style) -> String {
+    let max = rows
+        .iter()
+        .map(|r| r.1)
+        .max()
+        .unwrap_or_else(Duration::zero);
+    let label_width = rows.iter().map(|r| r.0.chars().count()).max().unwrap_or(0);
+    let values: Vec<String> = rows.iter().map(|r| format.format(r.1)).collect();
+    let value_width = values.iter().map(|v| v.chars().count()).max().unwrap_or(0);
+
+    let bar_width = match style.width {
+        Some(w) => w
+            .saturating_sub(label_width + value_width + 2)
+            .clamp(MIN_BAR_WIDTH, MAX_BAR_WIDTH),
+        None => DEFAULT_BAR_WIDTH,
+    };
+
+    let mut text = String::new();
+    for ((label, duration), value) in rows.iter().zip(values) {
+        let bar = bar(fraction(*duration, max), bar_width);
+        let padding = " ".repeat(bar_width - bar.chars().count());
+        text.push_str(&format!(
+            "{:<label_width$} {}{} {}\n",
+            label,
+            style.paint(&bar, project_colour(label.trim())),
+            padding,
+            value,
+        ));
+    }
+
+    text
+}
+
+/// Sparkline with a block for each value, scaled to the largest value.
+///
+/// Zero values are shown as spaces so they can be told apart from small values.
+pub fn sparkline(values: &[Duration]) -> String {
+    let max = values.iter().copied().max().unwrap_or_else(Duration::zero);
+
+    values
+        .iter()
+        .map(|v| {
+            if *v <= Duration::zero() {
+                return ' ';
+            }
+            let level = (fraction(*v, max) * SPARK_BLOCKS.len() as f64).ceil() as usize;
+            SPARK_BLOCKS[level.clamp(1, SPARK_BLOCKS.len()) - 1]
+        })
+        .collect()
+}
+
+/// Time tracked in each hour (UTC) of the day by `tasks`.
+pub fn hourly(tasks: &[Task]) -> [Duration; 24] {
+    let mut hours = [Duration::zero(); 24];
+
+    for task in tasks {
+        let mut start = *task.start_time();
+        while start < *task.end_time() {
+            let hour_start = start
+                .date_naive()
+                .and_time(NaiveTime::from_hms_opt(start.hour(), 0, 0).expect("valid hour"));
+            let next =
+                (Utc.from_utc_datetime(&hour_start) + Duration::hours(1)).min(*task.end_time());
+            hours[start.hour() as usize] += next - start;
+            start = next;
+        }
+    }
+
+    hours
+}
+
+/// Shade for an hour with `d` time tracked.
+fn shade(d: Duration) -> char {
+    if d <= Duration::zero() {
+        return SHADES[0];
+    }
+    let quarters = (fraction(d, Duration::hours(1)) * 4.0).ceil() as usize;
+    SHADES[quarters.clamp(1, 4)]
+}
+
+/// Heatmap of time tracked in each hour, with a row for every day from the
+/// first to last in `days`, followed by a sparkline of the daily totals.
+pub fn heatmap(
+    days: &BTreeMap<NaiveDate, Vec<Task>>,
+    format: DurationFormat,
+    style: &Style,
+) -> String {
+    let (first, last) = match (days.keys().next(), days.keys().next_back()) {
+        (Some(f), Some(l)) => (*f, *l),
+        _ => return String::new(),
+    };
+
+    let mut text = format!("{:10} ", "");
+    for hour in (0..24).step_by(6) {
+        text.push_str(&format!("{:<6}", hour));
+    }
+    text = text.trim_end().to_owned();
+    text.push('\n');
+
+    let mut totals = Vec::new();
+    for day in first.iter_days().take_while(|d| *d <= last) {
+        let hours = days.get(&day).map(|t| hourly(t)).unwrap_or_default();
+        let total = hours.iter().fold(Duration::zero(), |acc, h| acc + *h);
+        let cells: String = hours.iter().map(|h| shade(*h)).collect();
+
+        text.push_str(&format!(
+            "{} {} {}\n",
+            day.format("%a %e %b"),
+            style.paint(&cells, 36),
+            format.format(total)
+        ));
+        totals.push(total);
+    }
+    text.push_str(&format!("{:10} {}\n", "Daily", sparkline(&totals)));
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{Duration, NaiveDate, TimeZone, Utc};
+
+    use super::{bar, bar_chart, heatmap, hourly, sparkline};
+    use crate::duration::DurationFormat;
+    use crate::table::Style;
+    use crate::tasks::Task;
+
+    fn task(start: (u32, u32, u32), end: (u32, u32, u32)) -> Task {
+        Task::new(
+            "test".to_owned(),
+            Utc.with_ymd_and_hms(2022, 7, start.0, start.1, start.2, 0)
+                .unwrap(),
+            Utc.with_ymd_and_hms(2022, 7, end.0, end.1, end.2, 0)
+                .unwrap(),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_bar() {
+        let cases = [
+            (0.0, ""),
+            (1.0, "████"),
+            (0.5, "██"),
+            (0.5 + 1.0 / 32.0, "██▏"),
+            (0.25 + 7.0 / 32.0, "█▉"),
+        ];
+        for (fraction, expected) in cases {
+            assert_eq!(
+                bar(fraction, 4),
+                expected,
+                "testing: bar({}, 4) == {:?}",
+                fraction,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_bar_chart() {
+        let rows = [
+            ("a".to_owned(), Duration::hours(2)),
+            ("  a/b".to_owned(), Duration::hours(1)),
+        ];
+        let style = Style {
+            width: Some(22),
+            colour: false,
+        };
+        assert_eq!(
+            bar_chart(&rows, DurationFormat::Clock, &style),
+            "a     ██████████ 02:00\n  a/b █████      01:00\n"
+        );
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let values: Vec<Duration> = [0, 1, 4, 8].iter().map(|h| Duration::hours(*h)).collect();
+        assert_eq!(sparkline(&values), " ▁▄█");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn test_hourly() {
+        let hours = hourly(&[task((1, 9, 30), (1, 11, 15)), task((1, 11, 45), (1, 12, 0))]);
+        let minutes: Vec<i64> = hours[8..13].iter().map(|h| h.num_minutes()).collect();
+        assert_eq!(minutes, vec![0, 30, 60, 30, 0]);
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let mut days = BTreeMap::new();
+        days.insert(
+            NaiveDate::from_ymd_opt(2022, 7, 1).unwrap(),
+            vec![task((1, 0, 0), (1, 1, 10))],
+        );
+        days.insert(
+            NaiveDate::from_ymd_opt(2022, 7, 3).unwrap(),
+            vec![task((3, 23, 0), (3, 23, 20))],
+        );
+
+        let text = heatmap(&days, DurationFormat::Compact, &Style::plain());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "           0     6     12    18");
+        assert_eq!(lines[1], format!("Fri  1 Jul █░{} 1h10m", "·".repeat(22)));
+        assert_eq!(lines[2], format!("Sat  2 Jul {} 0s", "·".repeat(24)));
+        assert_eq!(lines[3], format!("Sun  3 Jul {}▒ 20m", "·".repeat(23)));
+        assert_eq!(lines[4], "Daily      █ ▃");
+    }
+}
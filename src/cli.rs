@@ -1,11 +1,42 @@
 //! Functionality for the command-line interface.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
 use anyhow::{anyhow, Result};
-use chrono::{
-    DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
-};
-use clap::{Parser, Subcommand, ValueEnum};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+
+use crate::backup::Backup;
+use crate::completions::{self, Shell};
+use crate::duration::DurationFormat;
+use crate::html_report;
+use crate::ical;
+use crate::importers::{self, ImportFormat};
+use crate::invoice::{self, InvoiceFormat};
+use crate::period::{self, parse_local_datetime, Period, PeriodKind};
+use crate::projects::{self, ProjectPattern};
+use crate::query::{RangeMatch, TaskQuery};
+use crate::reminders::{self, Daemon, SystemClock, Watcher};
+use crate::rounding::{Rounding, RoundingMode, RoundingScope};
+use crate::server::Server;
+use crate::status::{self, Status};
+use crate::table::{Cell, Column, Style, Table};
+use crate::targets::Targets;
+use crate::timekeep::Timekeep;
+use crate::{chart, report, CurrentTask, Project, Task};
+
+/// Time between checks for reminders by the daemon.
+const DAEMON_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
 
-use crate::{database, tasks, CurrentTask, DataFiles, Task};
+/// Port `serve` listens on when `--port` isn't given.
+const DEFAULT_PORT: u16 = 8765;
+
+/// Exit code of `view current` when no task is running.
+pub const EXIT_IDLE: u8 = 1;
+/// Exit code of any command which fails.
+pub const EXIT_ERROR: u8 = 2;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -13,6 +44,9 @@ use crate::{database, tasks, CurrentTask, DataFiles, Task};
 pub struct Cli {
     #[clap(subcommand)]
     pub command: Commands,
+    /// Style for displaying durations, overrides the config file
+    #[clap(long, value_enum, global = true)]
+    pub duration_format: Option<DurationFormat>,
 }
 
 #[derive(ValueEnum, Clone, Debug, Copy)]
@@ -22,9 +56,194 @@ pub enum ViewFilter {
     Day,
     Week,
     Month,
+    Quarter,
     Year,
 }
 
+impl ViewFilter {
+    /// Calendar period the filter covers, `None` for the current task or all tasks.
+    fn period_kind(&self) -> Option<PeriodKind> {
+        match self {
+            ViewFilter::Current | ViewFilter::All => None,
+            ViewFilter::Day => Some(PeriodKind::Day),
+            ViewFilter::Week => Some(PeriodKind::Week),
+            ViewFilter::Month => Some(PeriodKind::Month),
+            ViewFilter::Quarter => Some(PeriodKind::Quarter),
+            ViewFilter::Year => Some(PeriodKind::Year),
+        }
+    }
+}
+
+/// Options selecting tasks by their start time.
+#[derive(Args, Debug)]
+pub struct RangeArgs {
+    /// Shortcut timescale filter, relative to today
+    #[clap(value_enum)]
+    filter: Option<ViewFilter>,
+    /// Period for the filter instead of the current one, e.g. `yesterday`, `last`,
+    /// `2022-W09`, `2022-03` or `2022-Q1`
+    period: Option<String>,
+    /// Number of periods to move the filter by, e.g. -1 for the previous period
+    #[clap(long, default_value_t = 0, allow_hyphen_values = true)]
+    offset: i32,
+    /// Start date / time to get tasks from, if given filter is ignored
+    #[clap(short, long)]
+    from: Option<String>,
+    /// End date / time to get tasks before, if given filter is ignored
+    #[clap(short, long)]
+    to: Option<String>,
+}
+
+impl RangeArgs {
+    /// True if only the filter is given and it's for the current task.
+    fn is_current(&self, default: ViewFilter) -> bool {
+        self.from.is_none()
+            && self.to.is_none()
+            && matches!(self.filter.unwrap_or(default), ViewFilter::Current)
+    }
+
+    /// Start and end times for filtering tasks, from the `from` and `to` text
+    /// if either are given otherwise from the filter, or `default` if no
+    /// filter is given.
+    fn range(&self, default: ViewFilter) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+        if self.from.is_none() && self.to.is_none() {
+            return view_filter_shortcut(
+                self.filter.unwrap_or(default),
+                self.period.as_deref(),
+                self.offset,
+            );
+        }
+
+        let (min, max) = period::all_time();
+        let from = match &self.from {
+            Some(s) => parse_local_datetime(s)?,
+            None => min,
+        };
+        let to = match &self.to {
+            Some(s) => parse_local_datetime(s)?,
+            None => max,
+        };
+
+        if from > to {
+            return Err(anyhow!(
+                "from should be less than to, not {} and {}",
+                from.to_rfc2822(),
+                to.to_rfc2822()
+            ));
+        }
+
+        Ok((from, to))
+    }
+}
+
+/// Options selecting tasks by their project, tags and description.
+#[derive(Args, Debug)]
+pub struct FilterArgs {
+    /// Only include tasks for matching projects, e.g. `client/**` for all of a client's projects
+    #[clap(short, long)]
+    project: Option<ProjectPattern>,
+    /// Only include tasks with this tag, can be given multiple times to require all of them
+    #[clap(long = "tag")]
+    tags: Vec<String>,
+    /// Only include tasks whose description contains this text, ignoring case
+    #[clap(long)]
+    search: Option<String>,
+}
+
+impl FilterArgs {
+    /// Query for the tasks matching the options, started from `from` until `to`.
+    fn query(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> TaskQuery {
+        let query = TaskQuery::new()
+            .range(from, to)
+            .projects(self.project.clone())
+            .tags(&self.tags);
+        match &self.search {
+            Some(text) => query.search(text),
+            None => query,
+        }
+    }
+}
+
+/// Options overriding the rounding rule from the config file.
+#[derive(Args, Debug)]
+pub struct RoundingArgs {
+    /// Round durations to the nearest increment, or always up or down
+    #[clap(long, value_enum)]
+    round: Option<RoundingMode>,
+    /// Size of the rounding increment in minutes, 0 disables rounding
+    #[clap(long)]
+    round_minutes: Option<u32>,
+    /// Round each task's duration or only the total
+    #[clap(long, value_enum)]
+    round_scope: Option<RoundingScope>,
+}
+
+impl RoundingArgs {
+    /// Rounding rule from `config` with any options given on the command line.
+    pub fn resolve(&self, config: &Rounding) -> Rounding {
+        Rounding {
+            mode: self.round.unwrap_or(config.mode),
+            minutes: self.round_minutes.unwrap_or(config.minutes),
+            scope: self.round_scope.unwrap_or(config.scope),
+        }
+    }
+}
+
+/// Changes to a stored task, details not given are left unchanged.
+#[derive(Args, Debug)]
+pub struct TaskChanges {
+    /// New project name for the task
+    #[clap(short, long)]
+    project: Option<String>,
+    /// New date and time the task started
+    #[clap(short, long)]
+    start_time: Option<String>,
+    /// New date and time the task ended
+    #[clap(short, long)]
+    end_time: Option<String>,
+    /// New description, an empty description removes it
+    #[clap(short, long)]
+    description: Option<String>,
+    /// Replace the task's tags, can be given multiple times
+    #[clap(short, long = "tag")]
+    tags: Vec<String>,
+    /// Remove all of the task's tags
+    #[clap(long, conflicts_with = "tags")]
+    clear_tags: bool,
+}
+
+impl TaskChanges {
+    /// Copy of `task` with the changes applied.
+    fn apply(&self, task: &Task) -> Result<Task> {
+        let start_time = match &self.start_time {
+            Some(st) => parse_local_datetime(st)?,
+            None => *task.start_time(),
+        };
+        let end_time = match &self.end_time {
+            Some(et) => parse_local_datetime(et)?,
+            None => *task.end_time(),
+        };
+        let description = match &self.description {
+            Some(d) if d.is_empty() => None,
+            Some(d) => Some(d.to_owned()),
+            None => task.description().map(|d| d.to_owned()),
+        };
+        let tags = if self.clear_tags {
+            Vec::new()
+        } else if self.tags.is_empty() {
+            task.tags().to_vec()
+        } else {
+            self.tags.clone()
+        };
+        let project_name = match &self.project {
+            Some(p) => p.to_owned(),
+            None => task.project_name().to_owned(),
+        };
+
+        Ok(Task::new(project_name, start_time, end_time, description).with_tags(tags))
+    }
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Start a new task now, ending and saving any currently running tasks
@@ -40,6 +259,9 @@ pub enum Commands {
         /// Overwrite current task instead of ending it and starting a new one
         #[clap(short, long)]
         overwrite: bool,
+        /// Tag for the task, can be given multiple times
+        #[clap(short, long = "tag")]
+        tags: Vec<String>,
     },
     /// Save and end the current task
     End {
@@ -60,295 +282,1119 @@ pub enum Commands {
         /// Optional task description
         #[clap(short, long)]
         description: Option<String>,
+        /// Tag for the task, can be given multiple times
+        #[clap(short, long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Print a short status of the current task for shell prompts and status bars,
+    /// prints nothing if there's no current task
+    Status {
+        /// Placeholders {project}, {elapsed}, {start}, {description} and {tags}
+        /// are replaced with details of the current task
+        #[clap(short, long, default_value = status::DEFAULT_FORMAT)]
+        format: String,
+        /// Print JSON, with the formatted status in `text`, for status bars such as waybar
+        #[clap(long)]
+        json: bool,
+    },
+    /// Change details of a stored task, given by its ID shown by `view`
+    Edit {
+        /// ID of the task
+        id: i64,
+        #[clap(flatten)]
+        changes: TaskChanges,
+    },
+    /// Delete a stored task, given by its ID shown by `view`
+    Delete {
+        /// ID of the task
+        id: i64,
     },
     /// View current task or a group of tasks based on filtering the task start time
+    ///
+    /// `view current` exits with 0 if a task is running, 1 if not and 2 on errors.
     View {
-        /// Shortcut timescale filter, relative to today, for tasks to view
+        #[clap(flatten)]
+        range: RangeArgs,
+        #[clap(flatten)]
+        filter: FilterArgs,
+        /// List tasks without grouping them by day
+        #[clap(long)]
+        flat: bool,
+        /// Show charts of time per project and a heatmap of tracked hours
+        #[clap(long)]
+        chart: bool,
+        #[clap(flatten)]
+        rounding: RoundingArgs,
+    },
+    /// Summarise time spent, and amount billed, on each project (this week by default)
+    Report {
+        #[clap(flatten)]
+        range: RangeArgs,
+        #[clap(flatten)]
+        filter: FilterArgs,
+        /// Show a bar chart of time per project
+        #[clap(long)]
+        chart: bool,
+        /// Write the report, with charts and a timeline, to a standalone HTML file
+        #[clap(long)]
+        html: Option<PathBuf>,
+        #[clap(flatten)]
+        rounding: RoundingArgs,
+    },
+    /// Create a statement of time billed to a client (this month by default)
+    Invoice {
+        /// Client to create the statement for
+        client: String,
+        #[clap(flatten)]
+        range: RangeArgs,
+        /// Format of the statement
+        #[clap(long, value_enum, default_value = "markdown")]
+        format: InvoiceFormat,
+        /// File to write the statement to, printed if not given
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        #[clap(flatten)]
+        rounding: RoundingArgs,
+    },
+    /// Show time tracked against the daily and weekly targets, and the overtime balance
+    Balance,
+    /// Show statistics about tracked time, defaults to this month
+    Stats {
+        #[clap(flatten)]
+        range: RangeArgs,
+        /// Only include tasks for matching projects
+        #[clap(short, long)]
+        project: Option<ProjectPattern>,
+    },
+    /// Export tasks as iCalendar (.ics) events (this week by default), or
+    /// everything as a JSON backup
+    Export {
+        #[clap(flatten)]
+        range: RangeArgs,
+        /// Only export tasks for matching projects
+        #[clap(short, long)]
+        project: Option<ProjectPattern>,
+        /// File to write to, printed if not given
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// Write every task, project and the current task to a JSON backup file
+        #[clap(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = &["filter", "period", "from", "to", "project", "output"]
+        )]
+        all: Option<PathBuf>,
+    },
+    /// Import tasks from a calendar or another time tracker's export, or restore a JSON backup
+    ///
+    /// Tasks which have already been imported from the same file are skipped.
+    Import {
+        /// File to import
+        #[clap(required_unless_present = "restore")]
+        file: Option<PathBuf>,
+        /// Format of the file, guessed from its extension for .ics and .json files
+        #[clap(long, value_enum)]
+        format: Option<ImportFormat>,
+        /// Show the tasks which would be imported without storing them
+        #[clap(long)]
+        dry_run: bool,
+        /// Restore everything from a JSON backup file, created with `export --all`
+        #[clap(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = &["file", "format", "dry-run", "maps", "project"]
+        )]
+        restore: Option<PathBuf>,
+        /// Replace all existing data when restoring a backup
+        #[clap(long, requires = "restore", conflicts_with = "file")]
+        force: bool,
+        /// Project for calendar events in a category, in addition to the rules in the config
+        #[clap(long = "map", value_name = "CATEGORY=PROJECT")]
+        maps: Vec<String>,
+        /// Project for tasks without one, otherwise they're skipped
+        #[clap(short, long)]
+        project: Option<String>,
+    },
+    /// Manage project details, e.g. client and hourly rates
+    Project {
+        #[clap(subcommand)]
+        command: ProjectCommands,
+    },
+    /// Interactive terminal interface to start, stop, edit and delete tasks
+    Tui,
+    /// Keep running and send the reminders set in the config, about long
+    /// running tasks or no task during working hours
+    Daemon {
+        /// Check for reminders once and exit, e.g. when run from cron, reminders
+        /// due are given on every run
+        #[clap(long)]
+        once: bool,
+    },
+    /// Serve a JSON API over HTTP to start and stop tasks, edit tasks and
+    /// projects and fetch reports
+    Serve {
+        /// Port to listen on
+        #[clap(short, long, default_value_t = DEFAULT_PORT)]
+        port: u16,
+        /// Address to listen on, the default only accepts connections from this machine
+        #[clap(long, default_value = "127.0.0.1")]
+        address: String,
+    },
+    /// Print the completion script for a shell, e.g. `timekeep completions bash > ~/.local/share/bash-completion/completions/timekeep`
+    Completions {
         #[clap(value_enum)]
-        filter: Option<ViewFilter>,
-        /// Start date / time to get tasks from, if given filter is ignored
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProjectCommands {
+    /// Create or update a project, details not given are left unchanged
+    Set {
+        /// Name of the project
+        name: String,
+        /// Project description
+        #[clap(short, long)]
+        description: Option<String>,
+        /// Client the project is billed to
         #[clap(short, long)]
-        from: Option<String>,
-        /// End date / time to get tasks before, if given filter is ignored
+        client: Option<String>,
+        /// Hourly rate for the project
         #[clap(short, long)]
-        to: Option<String>,
+        rate: Option<f64>,
+        /// Currency of the hourly rates
+        #[clap(long)]
+        currency: Option<String>,
+    },
+    /// Set the hourly rate for tasks with a tag, overriding the project rate
+    Rate {
+        /// Name of the project
+        name: String,
+        /// Tag the rate applies to
+        tag: String,
+        /// Hourly rate, if not given the tag's rate is removed
+        rate: Option<f64>,
+    },
+    /// List all projects
+    List {
+        /// Only print names, including projects only used by tasks, most recently used first
+        #[clap(long)]
+        names: bool,
     },
-    // TODO Add edit command
 }
 
 pub fn start(
-    files: &DataFiles,
+    timekeep: &Timekeep,
     project_name: &str,
     start_time: &Option<String>,
     description: &Option<String>,
     overwrite: &bool,
+    tags: &[String],
 ) -> Result<()> {
-    if !*overwrite {
-        // End current task before starting a new one
-        match tasks::end_current_task(files.current_file(), files.database_file(), None, false)? {
-            Some(t) => println!("Ended task: {}", t),
-            None => (),
-        }
-    }
-
+    let format = timekeep.config().duration_format;
     let start_time = match &start_time {
         Some(st) => Some(parse_local_datetime(st)?),
         None => None,
     };
 
-    let t = tasks::start_task(
+    let started = timekeep.start(
         project_name,
         start_time,
-        description.as_ref(),
-        files.current_file(),
+        description.as_deref(),
+        tags,
+        *overwrite,
     )?;
-    println!("Started task: {}", t);
+    if let Some(t) = started.ended {
+        println!("Ended task: {}", t.summary(format));
+    }
+    println!("Started task: {}", started.started.summary(format));
 
     Ok(())
 }
 
-pub fn end(files: &DataFiles, end_time: &Option<String>, discard: &bool) -> Result<()> {
+pub fn end(timekeep: &Timekeep, end_time: &Option<String>, discard: &bool) -> Result<()> {
     let end_time = match &end_time {
         Some(et) => Some(parse_local_datetime(et)?),
         None => None,
     };
 
-    match tasks::end_current_task(
-        files.current_file(),
-        files.database_file(),
-        end_time,
-        *discard,
-    )? {
-        Some(t) => println!("Ended task: {}", t),
+    let format = timekeep.config().duration_format;
+    match timekeep.end(end_time, *discard)? {
+        Some(t) => println!("Ended task: {}", t.summary(format)),
         None => println!("No current task to end"),
     };
 
     Ok(())
 }
 
-/// Parse datetime string which doesn't include timezone, use local timezone.
-///
-/// If date isn't given then today is used, if time isn't given then 00:00:00
-/// is used.
-fn parse_local_datetime(text: &str) -> Result<DateTime<Utc>> {
-    // Attempt to parse datetime and fallback on parsing only date or time
-    let datetime = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
-        .or(NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M"));
-
-    let datetime = match datetime {
-        Ok(dt) => dt,
-        Err(_) => {
-            // Parse string as date only
-            let date = NaiveDate::parse_from_str(text, "%Y-%m-%d");
-
-            if date.is_ok() {
-                date.unwrap().and_hms(0, 0, 0)
-            } else {
-                // Parse string as time only and use today's date
-                let time = NaiveTime::parse_from_str(text, "%H:%M:%S")
-                    .or(NaiveTime::parse_from_str(text, "%H:%M"))?;
-
-                NaiveDateTime::new(Utc::today().naive_utc(), time)
-            }
-        }
-    };
-
-    Ok(Utc.from_local_datetime(&datetime).unwrap())
-}
-
 pub fn add(
-    files: &DataFiles,
+    timekeep: &Timekeep,
     project_name: &str,
     start_time: &str,
     end_time: &str,
     description: &Option<String>,
+    tags: &[String],
 ) -> Result<()> {
-    let start_time = parse_local_datetime(&start_time)?;
-    let end_time = parse_local_datetime(&end_time)?;
+    let start_time = parse_local_datetime(start_time)?;
+    let end_time = parse_local_datetime(end_time)?;
 
-    let description: Option<String> = match description {
-        Some(d) => Some(d.to_string()),
-        None => None,
-    };
+    let description: Option<String> = description.as_ref().map(|d| d.to_string());
 
-    let task = Task::new(project_name.to_owned(), start_time, end_time, description);
+    let task = Task::new(project_name.to_owned(), start_time, end_time, description)
+        .with_tags(tags.to_vec());
 
-    database::append_task(files.database_file(), &task)?;
-    println!("Added to database: {}", &task);
+    let task = timekeep.add(task)?;
+    println!(
+        "Added to database: {}",
+        task.summary(timekeep.config().duration_format)
+    );
 
     Ok(())
 }
 
-fn view_filter_shortcut(files: &DataFiles, filter: ViewFilter) -> Result<Vec<Task>> {
-    let today: DateTime<Utc> = Utc::today().and_hms(0, 0, 0);
+/// Change the details of stored task `id` which are given, others are left unchanged.
+pub fn edit(timekeep: &Timekeep, id: i64, changes: &TaskChanges) -> Result<()> {
+    let task = timekeep
+        .task(id)?
+        .ok_or_else(|| anyhow!("no task with id {}", id))?;
 
-    match filter {
-        ViewFilter::Current => Err(anyhow!("cannot view current task in table")),
+    let task = timekeep.edit(id, changes.apply(&task)?)?;
+    println!(
+        "Updated task: {}",
+        task.summary(timekeep.config().duration_format)
+    );
 
-        ViewFilter::All => database::extract_all_tasks(&files.database_file),
+    Ok(())
+}
 
-        ViewFilter::Day => {
-            database::extract_tasks(&files.database_file, today, today + Duration::days(1))
-        }
+/// Delete stored task `id`.
+pub fn delete(timekeep: &Timekeep, id: i64) -> Result<()> {
+    let task = timekeep.delete(id)?;
+    println!(
+        "Deleted task: {}",
+        task.summary(timekeep.config().duration_format)
+    );
 
-        ViewFilter::Week => {
-            let mon = NaiveDate::from_isoywd(today.year(), today.iso_week().week(), Weekday::Mon)
-                .and_hms(0, 0, 0);
-            let mon = Utc.from_utc_datetime(&mon);
+    Ok(())
+}
 
-            database::extract_tasks(&files.database_file, mon, mon + Duration::days(7))
+/// Start and end times for filtering tasks with the shortcut `filter`.
+///
+/// `period` selects a period other than the current one, see `Period::parse`,
+/// which is then moved by `offset` periods.
+fn view_filter_shortcut(
+    filter: ViewFilter,
+    period: Option<&str>,
+    offset: i32,
+) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+    let kind = match filter {
+        ViewFilter::Current => return Err(anyhow!("cannot view current task in table")),
+        ViewFilter::All if period.is_some() || offset != 0 => {
+            return Err(anyhow!(
+                "cannot give period or offset when viewing all tasks"
+            ))
         }
+        ViewFilter::All => return Ok(period::all_time()),
+        f => f
+            .period_kind()
+            .expect("only current and all have no period"),
+    };
 
-        ViewFilter::Month => {
-            let first = today
-                .with_day(1)
-                .expect("always valid because all months have a day 1");
+    let today = Utc::now().date_naive();
+    let period = match period {
+        Some(p) => Period::parse(kind, p, today)?,
+        None => Period::containing(kind, today),
+    };
+    let period = period
+        .offset(offset)
+        .ok_or_else(|| anyhow!("offset {} is outside the supported dates", offset))?;
 
-            let last = match first.month() {
-                12 => first // First day of the next year
-                    .with_year(today.year() + 1)
-                    .expect("invalid year")
-                    .with_month(1)
-                    .expect("hardcoded valid month"),
+    Ok(period.range())
+}
 
-                m => first // First day of the next month
-                    .with_month(m + 1)
-                    .expect("m + 1 will always be a valid month"),
-            };
+/// Print the status of the current task using `format`, or nothing if there isn't one.
+pub fn status(timekeep: &Timekeep, format: &str, json: bool) -> Result<()> {
+    let config = timekeep.config();
+    let task = match timekeep.peek_current()? {
+        Some(t) => t,
+        None => return Ok(()),
+    };
 
-            database::extract_tasks(&files.database_file, first, last)
+    if json {
+        let status = Status::new(format, &task, Utc::now(), config.duration_format)?;
+        println!("{}", serde_json::to_string(&status)?);
+    } else {
+        let text = status::format_status(format, &task, Utc::now(), config.duration_format)?;
+        println!("{}", text);
+    }
+
+    Ok(())
+}
+
+/// View task, or group of tasks, based on start time filtering
+pub fn view(
+    timekeep: &Timekeep,
+    range: &RangeArgs,
+    filter: &FilterArgs,
+    flat: bool,
+    chart: bool,
+    rounding: &RoundingArgs,
+) -> Result<ExitCode> {
+    let config = timekeep.config();
+    let rounding = rounding.resolve(&config.rounding);
+
+    // Use filter if after or before aren't given
+    if range.is_current(ViewFilter::Current) {
+        return view_current(timekeep);
+    }
+
+    let (start, end) = range.range(ViewFilter::Current)?;
+    if range.from.is_some() || range.to.is_some() {
+        println!(
+            "Showing results from {} - {}",
+            start.to_rfc2822(),
+            end.to_rfc2822()
+        );
+    }
+
+    let tasks = timekeep.query(&filter.query(start, end))?;
+    let style = Style::detect();
+    if flat || (end - start <= Duration::days(1)) {
+        display_tasks(&tasks, &rounding, config.duration_format, &style);
+    } else {
+        display_tasks_by_day(
+            &tasks,
+            start,
+            end,
+            &rounding,
+            config.duration_format,
+            &style,
+        );
+    }
+
+    if chart {
+        let mut projects: BTreeMap<&str, Duration> = BTreeMap::new();
+        for t in &tasks {
+            *projects
+                .entry(t.project_name())
+                .or_insert_with(Duration::zero) += t.duration();
         }
+        let rows: Vec<(String, Duration)> = projects
+            .into_iter()
+            .map(|(p, d)| (p.to_owned(), d))
+            .collect();
+        print!(
+            "\n{}",
+            chart::bar_chart(&rows, config.duration_format, &style)
+        );
+        print!(
+            "\n{}",
+            chart::heatmap(
+                &report::group_by_day(&tasks, start, end),
+                config.duration_format,
+                &style
+            )
+        );
+    }
 
-        ViewFilter::Year => {
-            let first = today
-                .with_month(1)
-                .expect("hardcoded valid month")
-                .with_day(1)
-                .expect("hardcoded valid day");
-            let last = first.with_year(first.year() + 1).expect("invalid year");
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Print the current task, or the last finished task if there isn't one, and
+/// the total time tracked today.
+///
+/// Exits with `EXIT_IDLE` if no task is running, so scripts can tell idle and
+/// running apart from errors.
+fn view_current(timekeep: &Timekeep) -> Result<ExitCode> {
+    let config = timekeep.config();
+    let current = timekeep.current()?;
 
-            database::extract_tasks(&files.database_file, first, last)
+    let now = Utc::now();
+    let (start, end) = Period::containing(PeriodKind::Day, now.date_naive()).range();
+    let query = TaskQuery::new()
+        .range(start, end)
+        .range_match(RangeMatch::Overlap);
+    let total = day_total(&timekeep.query(&query)?, current.as_ref(), now);
+
+    let exit_code = match &current {
+        Some(t) => {
+            println!("Current task: {}", t.summary(config.duration_format));
+            ExitCode::SUCCESS
+        }
+        None => {
+            println!("No task running");
+            if let Some(t) = timekeep.last_task()? {
+                println!("Last task:    {}", t.summary(config.duration_format));
+            }
+            ExitCode::from(EXIT_IDLE)
         }
+    };
+    println!("Today:        {}", config.duration_format.format(total));
+
+    Ok(exit_code)
+}
+
+/// Time spent on the day containing `now`, on `tasks` and the `current`
+/// task, counting only the parts of tasks within that day.
+fn day_total(tasks: &[Task], current: Option<&CurrentTask>, now: DateTime<Utc>) -> Duration {
+    let (start, end) = Period::containing(PeriodKind::Day, now.date_naive()).range();
+    let current = current
+        .filter(|t| *t.start_time() < now)
+        .map(|t| Task::new(t.project_name().to_owned(), *t.start_time(), now, None));
+    let tasks: Vec<Task> = tasks.iter().cloned().chain(current).collect();
+
+    report::group_by_day(&tasks, start, end)
+        .values()
+        .flatten()
+        .map(|t| t.duration())
+        .fold(Duration::zero(), |a, b| a + b)
+}
+
+/// Print summary of time spent, and amount billed, on each project.
+///
+/// Totals are rolled up each level of the project hierarchy, with sub-projects
+/// indented below their parent.
+pub fn report(
+    timekeep: &Timekeep,
+    range: &RangeArgs,
+    filter: &FilterArgs,
+    chart: bool,
+    html: &Option<PathBuf>,
+    rounding: &RoundingArgs,
+) -> Result<()> {
+    let config = timekeep.config();
+    let rounding = rounding.resolve(&config.rounding);
+
+    let (start, end) = range.range(ViewFilter::Week)?;
+    let report = timekeep.report(&filter.query(start, end), &rounding)?;
+
+    if let Some(path) = html {
+        let text = html_report::render_html_report(
+            start,
+            end,
+            &report.summaries,
+            &report.days(),
+            config.duration_format,
+        );
+        fs::write(path, text)?;
+        println!("Written report to {}", path.display());
+        return Ok(());
+    }
+
+    let mut table = Table::new(vec![
+        Column::new("Project Name"),
+        Column::new("Duration"),
+        Column::new("Amount").right(),
+    ]);
+    let roll_up = report.roll_up();
+    for r in &roll_up {
+        let amounts: Vec<String> = r
+            .amounts
+            .iter()
+            .map(|(c, a)| report::format_amount(*a, Some(c)))
+            .collect();
+
+        let mut name = Cell::project(&r.project);
+        name.indent(r.depth);
+        table.add_row(vec![
+            name,
+            config.duration_format.format(r.duration).into(),
+            amounts.join(", ").into(),
+        ]);
+    }
+    let style = Style::detect();
+    print!("{}", table.render(&style));
+    if chart {
+        let rows: Vec<(String, Duration)> = roll_up
+            .iter()
+            .map(|r| (format!("{}{}", "  ".repeat(r.depth), r.project), r.duration))
+            .collect();
+        print!(
+            "\n{}\n",
+            chart::bar_chart(&rows, config.duration_format, &style)
+        );
     }
+
+    println!("Total: {}", config.duration_format.format(report.total()));
+    for (currency, amount) in report.currency_totals() {
+        println!(
+            "Total amount: {}",
+            report::format_amount(amount, Some(&currency))
+        );
+    }
+
+    Ok(())
 }
 
-/// View task, or group of tasks, based on start time filtering
-pub fn view(
-    files: &DataFiles,
-    filter: Option<ViewFilter>,
-    from: &Option<String>,
-    to: &Option<String>,
+/// Write statement of time billed to `client` to `output`, or print it if not given.
+pub fn invoice(
+    timekeep: &Timekeep,
+    client: &str,
+    range: &RangeArgs,
+    format: InvoiceFormat,
+    output: &Option<PathBuf>,
+    rounding: &RoundingArgs,
 ) -> Result<()> {
-    let tasks = if from.is_none() & to.is_none() {
-        // Use filter if after or before aren't given
-        let filter = filter.unwrap_or(ViewFilter::Current);
-
-        if matches!(filter, ViewFilter::Current) {
-            let t = CurrentTask::load(files.current_file())?;
-            println!("Current task: {}", t);
-            return Ok(());
+    let rounding = rounding.resolve(&timekeep.config().rounding);
+
+    let (start, end) = range.range(ViewFilter::Month)?;
+    let projects = timekeep.projects()?;
+    if !projects.iter().any(|p| p.client() == Some(client)) {
+        return Err(anyhow!("no projects found for client {:?}", client));
+    }
+
+    // Sub-projects are billed to the client of their parent project
+    let tasks: Vec<Task> = timekeep
+        .query(&TaskQuery::new().range(start, end))?
+        .into_iter()
+        .filter(|t| {
+            projects::find_project(&projects, t.project_name()).and_then(|p| p.client())
+                == Some(client)
+        })
+        .collect();
+    let summaries = report::summarise_projects(&tasks, &projects, &rounding);
+    let text = invoice::render_invoice(client, start, end, &summaries, format);
+
+    match output {
+        Some(path) => {
+            fs::write(path, text)?;
+            println!("Written invoice to {}", path.display());
         }
+        None => print!("{}", text),
+    };
 
-        view_filter_shortcut(files, filter)?
+    Ok(())
+}
+
+/// Format duration with a leading sign, e.g. for overtime (+) or undertime (-).
+fn signed_duration(d: Duration, format: DurationFormat) -> String {
+    if d < Duration::zero() {
+        format.format(d)
     } else {
-        let from = match from {
-            Some(s) => parse_local_datetime(s)?,
-            None => Utc.ymd(0, 1, 1).and_hms(0, 0, 0),
-        };
-        let to = match to {
-            Some(s) => parse_local_datetime(s)?,
-            None => Utc.ymd(9999, 1, 1).and_hms(0, 0, 0),
-        };
+        format!("+{}", format.format(d))
+    }
+}
+
+/// Total time tracked on tasks starting from `from` up to, but excluding, `to`.
+///
+/// Includes the elapsed time of the current task, if it started in the range.
+fn tracked_between(timekeep: &Timekeep, from: NaiveDate, to: NaiveDate) -> Result<Duration> {
+    let from = Utc.from_utc_datetime(&from.and_time(NaiveTime::MIN));
+    let to = Utc.from_utc_datetime(&to.and_time(NaiveTime::MIN));
+
+    let mut total = timekeep
+        .query(&TaskQuery::new().range(from, to))?
+        .iter()
+        .fold(Duration::zero(), |acc, t| acc + t.duration());
+    if let Some(current) = timekeep.current()? {
+        if (from..to).contains(current.start_time()) {
+            total += current.duration();
+        }
+    }
+
+    Ok(total)
+}
+
+/// Print time tracked today and this week against the targets, and the
+/// running overtime balance since the configured start date.
+pub fn balance(timekeep: &Timekeep) -> Result<()> {
+    let config = timekeep.config();
+    let targets: &Targets = &config.targets;
+    // Check targets are configured before printing anything
+    let week_target = targets.weekly_target()?;
 
+    let today = Utc::now().date_naive();
+    let tomorrow = today + Duration::days(1);
+    let monday = Period::current(PeriodKind::Week).start();
+
+    let day_tracked = tracked_between(timekeep, today, tomorrow)?;
+    let day_target = targets.daily_target(today)?;
+    println!(
+        "Today:     {: <15} of {: <15} ({})",
+        config.duration_format.format(day_tracked),
+        config.duration_format.format(day_target),
+        signed_duration(day_tracked - day_target, config.duration_format)
+    );
+
+    let week_tracked = tracked_between(timekeep, monday, tomorrow)?;
+    println!(
+        "This week: {: <15} of {: <15} ({})",
+        config.duration_format.format(week_tracked),
+        config.duration_format.format(week_target),
+        signed_duration(week_tracked - week_target, config.duration_format)
+    );
+
+    // Today isn't finished so the balance only covers previous days
+    if let Some(start) = targets.start_date {
+        if start < today {
+            let tracked = tracked_between(timekeep, start, today)?;
+            let target = targets.target_between(start, today)?;
+            println!(
+                "Balance since {} (excluding today): {}",
+                start.format("%Y-%m-%d"),
+                signed_duration(tracked - target, config.duration_format)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Print statistics about the tasks in the range, e.g. average task length and
+/// time tracked by weekday and hour.
+pub fn stats(
+    timekeep: &Timekeep,
+    range: &RangeArgs,
+    project: &Option<ProjectPattern>,
+) -> Result<()> {
+    let format = timekeep.config().duration_format;
+    let (start, end) = range.range(ViewFilter::Month)?;
+    let stats = timekeep.statistics(start, end, project.as_ref())?;
+    if stats.task_count == 0 {
+        println!("No tasks found");
+        return Ok(());
+    }
+
+    println!("Tasks:            {}", stats.task_count);
+    println!("Total:            {}", format.format(stats.total));
+    println!("Average task:     {}", format.format(stats.average));
+    if let Some(t) = &stats.longest {
+        println!("Longest task:     {}", t.summary(format));
+    }
+    if let Some((day, duration)) = stats.busiest_day() {
         println!(
-            "Showing results from {} - {}",
-            from.to_rfc2822(),
-            to.to_rfc2822()
+            "Busiest day:      {} ({})",
+            day.format("%a %Y-%m-%d"),
+            format.format(duration)
         );
+    }
+    if let Some((day, count)) = stats.most_switches() {
+        println!(
+            "Context switches: {:.1} per day, most {} on {}",
+            stats.average_switches(),
+            count,
+            day.format("%a %Y-%m-%d")
+        );
+    }
+    if let Some(streak) = stats.longest_streak() {
+        println!(
+            "Longest streak:   {} days ({} to {})",
+            streak.days(),
+            streak.first.format("%Y-%m-%d"),
+            streak.last.format("%Y-%m-%d")
+        );
+    }
+    if let Some(streak) = stats.current_streak(Utc::now().date_naive()) {
+        println!("Current streak:   {} days", streak.days());
+    }
 
-        if from > to {
-            return Err(anyhow!(
-                "from should be less than to, not {} and {}",
-                from.to_rfc2822(),
-                to.to_rfc2822()
-            ));
+    let style = Style::detect();
+    let mut table = Table::new(vec![Column::new("Weekday"), Column::new("Duration")]);
+    for (day, duration) in &stats.by_weekday {
+        table.add_row(vec![
+            day.to_string().into(),
+            format.format(*duration).into(),
+        ]);
+    }
+    print!("\n{}", table.render(&style));
+
+    let mut table = Table::new(vec![Column::new("Hour"), Column::new("Duration")]);
+    for (hour, duration) in stats.by_hour.iter().enumerate() {
+        if !duration.is_zero() {
+            table.add_row(vec![
+                format!("{:02}:00", hour).into(),
+                format.format(*duration).into(),
+            ]);
         }
+    }
+    print!("\n{}", table.render(&style));
+
+    Ok(())
+}
 
-        database::extract_tasks(&files.database_file, from, to)?
+/// Write tasks in the range as calendar events to `output`, or print them if not given.
+pub fn export(
+    timekeep: &Timekeep,
+    range: &RangeArgs,
+    project: &Option<ProjectPattern>,
+    output: &Option<PathBuf>,
+) -> Result<()> {
+    let (start, end) = range.range(ViewFilter::Week)?;
+    let query = TaskQuery::new().range(start, end).projects(project.clone());
+    let tasks = timekeep.query(&query)?;
+    let text = ical::render_calendar(&tasks);
+
+    match output {
+        Some(path) => {
+            fs::write(path, text)?;
+            println!("Written {} tasks to {}", tasks.len(), path.display());
+        }
+        None => print!("{}", text),
     };
 
-    display_tasks(&tasks);
+    Ok(())
+}
+
+/// Write backup of everything in the database to `path`.
+pub fn export_backup(timekeep: &Timekeep, path: &Path) -> Result<()> {
+    let backup = timekeep.backup()?;
+    fs::write(path, backup.to_json()?)?;
+    println!(
+        "Written {} tasks and {} projects to {}",
+        backup.tasks.len(),
+        backup.projects.len(),
+        path.display()
+    );
 
     Ok(())
 }
 
-/// Print tasks to screen in a simple table structure.
-fn display_tasks(tasks: &Vec<Task>) {
-    println!("Found {} tasks", tasks.len());
+/// Restore backup from `path`, replacing any existing data if `force` is true.
+pub fn restore_backup(timekeep: &Timekeep, path: &Path, force: bool) -> Result<()> {
+    let backup = Backup::from_json(&fs::read_to_string(path)?)?;
+    timekeep.restore(&backup, force)?;
     println!(
-        "| {: <17} | {: <17} | {: <15} | {: <25} | {:0.50}",
-        "From", "To", "Duration", "Project Name", "Description"
+        "Restored {} tasks and {} projects from {}",
+        backup.tasks.len(),
+        backup.projects.len(),
+        path.display()
     );
-    for t in tasks {
-        println!("{}", t);
+
+    Ok(())
+}
+
+/// Add tasks from `file`, with projects for calendar events chosen by the
+/// category rules from the config and `maps`.
+///
+/// If `dry_run` is true the tasks are listed, showing which are duplicates,
+/// but not stored.
+pub fn import(
+    timekeep: &Timekeep,
+    file: &Path,
+    format: Option<ImportFormat>,
+    dry_run: bool,
+    maps: &[String],
+    project: &Option<String>,
+) -> Result<()> {
+    let format = format
+        .or_else(|| ImportFormat::from_path(file))
+        .ok_or_else(|| anyhow!("can't tell the format of {}, use --format", file.display()))?;
+    if !maps.is_empty() && format != ImportFormat::Ics {
+        return Err(anyhow!("--map can only be used for calendar files"));
+    }
+
+    let config = timekeep.config();
+    let mut rules = config.calendar.clone();
+    for map in maps {
+        let (category, project) = map
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid category map {:?}, expected CATEGORY=PROJECT", map))?;
+        rules
+            .categories
+            .insert(category.to_owned(), project.to_owned());
+    }
+    if project.is_some() {
+        rules.default_project = project.clone();
+    }
+
+    let imported = importers::read_tasks(format, &fs::read_to_string(file)?, &rules)?;
+    let added = timekeep.import(&imported.tasks, dry_run)?;
+    let new = added.iter().filter(|a| **a).count();
+    let duplicates = added.len() - new;
+
+    if dry_run {
+        let mut table = Table::new(vec![
+            Column::new("Status"),
+            Column::new("From"),
+            Column::new("To"),
+            Column::new("Duration"),
+            Column::new("Project Name").truncate(),
+            Column::new("Tags").truncate(),
+            Column::new("Description").truncate(),
+        ]);
+        for (i, is_new) in imported.tasks.iter().zip(&added) {
+            let t = &i.task;
+            table.add_row(vec![
+                if *is_new { "new" } else { "duplicate" }.into(),
+                t.start_time()
+                    .naive_local()
+                    .format("%R %v")
+                    .to_string()
+                    .into(),
+                t.end_time()
+                    .naive_local()
+                    .format("%R %v")
+                    .to_string()
+                    .into(),
+                config.duration_format.format(t.duration()).into(),
+                Cell::project(t.project_name()),
+                t.tags().join(", ").into(),
+                t.description().unwrap_or_default().into(),
+            ]);
+        }
+        print!("{}", table.render(&Style::detect()));
+        println!(
+            "Would import {} tasks, skipping {} duplicates and {} without a project",
+            new, duplicates, imported.skipped
+        );
+    } else {
+        println!(
+            "Imported {} tasks, skipped {} duplicates and {} without a project",
+            new, duplicates, imported.skipped
+        );
     }
+
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use chrono::{NaiveDate, TimeZone, Utc};
+/// Create or update a project, only changing the details which are given.
+pub fn project_set(
+    timekeep: &Timekeep,
+    name: &str,
+    description: &Option<String>,
+    client: &Option<String>,
+    rate: &Option<f64>,
+    currency: &Option<String>,
+) -> Result<()> {
+    let mut project = timekeep
+        .project(name)?
+        .unwrap_or_else(|| Project::new(name.to_owned()));
 
-    /// Test parsing text with date and time.
-    #[test]
-    fn datetime_parse_valid() {
-        let mut tests = Vec::new();
-        tests.push((
-            "2022-02-01 13:14:15",
-            NaiveDate::from_ymd(2022, 2, 1).and_hms(13, 14, 15),
-        ));
-        tests.push((
-            "2022-02-01 01:02",
-            NaiveDate::from_ymd(2022, 2, 1).and_hms(1, 2, 0),
-        ));
-        tests.push((
-            "2022-2-1 1:2:3",
-            NaiveDate::from_ymd(2022, 2, 1).and_hms(1, 2, 3),
-        ));
-        tests.push((
-            "2022-2-1 1:2",
-            NaiveDate::from_ymd(2022, 2, 1).and_hms(1, 2, 0),
+    if description.is_some() {
+        project.set_description(description.clone());
+    }
+    if client.is_some() {
+        project.set_client(client.clone());
+    }
+    if rate.is_some() {
+        project.set_rate(*rate);
+    }
+    if currency.is_some() {
+        project.set_currency(currency.clone());
+    }
+
+    timekeep.save_project(&project)?;
+    println!("Saved project: {}", project.name());
+
+    Ok(())
+}
+
+/// Set, or remove if `rate` isn't given, the hourly rate for tasks with `tag` in a project.
+pub fn project_rate(timekeep: &Timekeep, name: &str, tag: &str, rate: &Option<f64>) -> Result<()> {
+    let mut project = timekeep
+        .project(name)?
+        .ok_or_else(|| anyhow!("project {:?} doesn't exist", name))?;
+
+    project.set_tag_rate(tag, *rate);
+    timekeep.save_project(&project)?;
+
+    match rate {
+        Some(r) => println!("Set rate for {:?} tasks in {} to {}", tag, name, r),
+        None => println!("Removed rate for {:?} tasks in {}", tag, name),
+    };
+
+    Ok(())
+}
+
+/// Send reminders set in the config, checking every minute unless `once`.
+pub fn daemon(timekeep: &Timekeep, once: bool) -> Result<()> {
+    let config = timekeep.config();
+    let watcher = Watcher::new(
+        config.reminders.clone(),
+        config.targets.working_days.clone(),
+    );
+    if !watcher.is_enabled() {
+        return Err(anyhow!(
+            "no reminders configured, set `long_task_minutes` or `working_hours` in the config's `reminders`"
         ));
-        tests.push(("2022-2-1", NaiveDate::from_ymd(2022, 2, 1).and_hms(0, 0, 0)));
+    }
 
-        for (s, t) in tests {
-            let t = Utc.from_local_datetime(&t).unwrap();
+    let mut daemon = Daemon::new(
+        timekeep,
+        watcher,
+        Box::new(SystemClock),
+        reminders::notifier(&config.reminders.notifier, timekeep.files().data_folder()),
+    );
+    if once {
+        daemon.tick()?;
+        return Ok(());
+    }
 
-            assert_eq!(
-                super::parse_local_datetime(s).unwrap(),
-                t,
-                "testing: parse_local_datetime({}) == {:?}",
-                s,
-                t
-            );
+    daemon.run(DAEMON_INTERVAL)
+}
+
+pub fn serve(timekeep: &Timekeep, address: &str, port: u16) -> Result<()> {
+    let server = Server::bind(&format!("{}:{}", address, port))?;
+    match server.address() {
+        Some(address) => println!("Listening on http://{}", address),
+        None => println!("Listening on {}:{}", address, port),
+    }
+    server.serve(timekeep);
+
+    Ok(())
+}
+
+/// Print the completion script for `shell`.
+pub fn completions(shell: Shell) -> Result<()> {
+    print!("{}", completions::script(shell, &mut Cli::command()));
+    Ok(())
+}
+
+/// Print all stored projects, or with `names` the name of every project
+/// used by a task or stored, one per line and most recently used first.
+pub fn project_list(timekeep: &Timekeep, names: bool) -> Result<()> {
+    if names {
+        for project in timekeep.recent_projects()? {
+            println!("{}", project);
         }
+        return Ok(());
     }
 
-    /// Test parsing text with time only.
-    #[test]
-    fn datetime_parse_time() {
-        let mut tests = Vec::new();
-        tests.push(("11:12:1", Utc::today().and_hms(11, 12, 1).naive_utc()));
-        tests.push(("11:12", Utc::today().and_hms(11, 12, 0).naive_utc()));
+    let projects = timekeep.projects()?;
 
-        for (s, t) in tests {
-            let t = Utc.from_local_datetime(&t).unwrap();
+    let mut table = Table::new(vec![
+        Column::new("Project Name"),
+        Column::new("Client"),
+        Column::new("Rate").right(),
+        Column::new("Description").truncate(),
+    ]);
+    for p in projects {
+        let mut rates: Vec<String> = p
+            .rate()
+            .map(|r| report::format_amount(r, p.currency()))
+            .into_iter()
+            .collect();
+        rates.extend(
+            p.tag_rates()
+                .iter()
+                .map(|(t, r)| format!("{}: {}", t, report::format_amount(*r, p.currency()))),
+        );
+
+        table.add_row(vec![
+            Cell::project(p.name()),
+            p.client().unwrap_or_default().into(),
+            rates.join(", ").into(),
+            p.description().unwrap_or_default().into(),
+        ]);
+    }
+    print!("{}", table.render(&Style::detect()));
+
+    Ok(())
+}
+
+/// Table of tasks, with durations rounded.
+fn task_table(tasks: &[Task], rounding: &Rounding, format: DurationFormat) -> Table {
+    let mut table = Table::new(vec![
+        Column::new("ID").right(),
+        Column::new("From"),
+        Column::new("To"),
+        Column::new("Duration"),
+        Column::new("Project Name"),
+        Column::new("Tags").truncate(),
+        Column::new("Description").truncate(),
+    ]);
+
+    for t in tasks {
+        table.add_row(vec![
+            t.id().map(|i| i.to_string()).unwrap_or_default().into(),
+            t.start_time()
+                .naive_local()
+                .format("%R %v")
+                .to_string()
+                .into(),
+            t.end_time()
+                .naive_local()
+                .format("%R %v")
+                .to_string()
+                .into(),
+            format.format(rounding.task(t.duration())).into(),
+            Cell::project(t.project_name()),
+            t.tags().join(", ").into(),
+            t.description().unwrap_or_default().into(),
+        ]);
+    }
+
+    table
+}
+
+/// Print tasks to screen in a table, with durations rounded.
+fn display_tasks(tasks: &[Task], rounding: &Rounding, format: DurationFormat, style: &Style) {
+    println!("Found {} tasks", tasks.len());
+    print!("{}", task_table(tasks, rounding, format).render(style));
+    println!(
+        "Total: {}",
+        format.format(rounding.total(tasks.iter().map(|t| t.duration())))
+    );
+}
+
+/// Print tasks grouped under a heading for each day, with subtotals for each
+/// day and the total for all days at the end.
+fn display_tasks_by_day(
+    tasks: &[Task],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    rounding: &Rounding,
+    format: DurationFormat,
+    style: &Style,
+) {
+    println!("Found {} tasks", tasks.len());
+
+    let days = report::group_by_day(tasks, from, to);
+    for (day, parts) in &days {
+        println!(
+            "\n{}",
+            style.paint(&day.format("%A %e %B %Y").to_string(), 1)
+        );
+        print!("{}", task_table(parts, rounding, format).render(style));
+        println!(
+            "Day total: {}",
+            format.format(rounding.total(parts.iter().map(|t| t.duration())))
+        );
+    }
+
+    let total = rounding.total(days.values().flatten().map(|t| t.duration()));
+    println!("\nTotal: {}", format.format(total));
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::day_total;
+    use crate::{CurrentTask, Task};
+
+    #[test]
+    fn test_day_total() {
+        let midnight = Utc.with_ymd_and_hms(2022, 7, 2, 0, 0, 0).unwrap();
+        let now = midnight + Duration::hours(2);
+        let task = |start: i64, end: i64| {
+            Task::new(
+                "acme".to_owned(),
+                midnight + Duration::minutes(start),
+                midnight + Duration::minutes(end),
+                None,
+            )
+        };
+        let current = |start: i64| {
+            CurrentTask::new("acme".to_owned(), midnight + Duration::minutes(start), None)
+        };
 
+        let cases = [
+            (vec![task(30, 60)], None, 30),
+            // Only the parts of tasks crossing midnight after midnight are counted
+            (vec![task(-120, 30)], None, 30),
+            (vec![task(-120, -60), task(10, 20)], None, 10),
+            (vec![], Some(current(-60)), 120),
+            (vec![task(-30, 15)], Some(current(60)), 15 + 60),
+            (vec![], Some(current(180)), 0),
+        ];
+        for (tasks, current, expected) in cases {
             assert_eq!(
-                super::parse_local_datetime(s).unwrap(),
-                t,
-                "testing: parse_local_datetime({}) == {:?}",
-                s,
-                t
+                day_total(&tasks, current.as_ref(), now),
+                Duration::minutes(expected),
+                "testing: day_total({:?}, {:?})",
+                tasks,
+                current
             );
         }
     }
@@ -0,0 +1,124 @@
+//! Shell completion scripts, with project names completed from the database.
+use clap::{Command, ValueEnum};
+use clap_complete::{generate, Shell as GeneratorShell};
+
+/// Shell to generate completions for.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Completes project names for `start` and `add` and after `--project`/`-p`,
+/// otherwise falls back to the generated completions.
+const BASH_PROJECTS: &str = r#"
+_timekeep_projects() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    local prev="${COMP_WORDS[COMP_CWORD-1]}"
+    if [[ ${prev} == "--project" || ${prev} == "-p" ]] ||
+        [[ ${COMP_CWORD} -eq 2 && ${cur} != -* && ( ${COMP_WORDS[1]} == "start" || ${COMP_WORDS[1]} == "add" ) ]]; then
+        local IFS=$'\n'
+        COMPREPLY=( $(compgen -W "$(timekeep project list --names 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+    _timekeep "$@"
+}
+
+# Keep projects in most recently used order, nosort needs bash 4.4
+complete -F _timekeep_projects -o bashdefault -o default -o nosort timekeep 2>/dev/null ||
+    complete -F _timekeep_projects -o bashdefault -o default timekeep
+"#;
+
+/// Replaces the final call of the generated script, which is renamed to
+/// `_timekeep_generated` so the autoloaded `_timekeep` is this wrapper.
+const ZSH_PROJECTS: &str = r#"
+_timekeep() {
+    if [[ ${words[CURRENT-1]} == (--project|-p) ]] ||
+        [[ ${CURRENT} -eq 3 && ${PREFIX} != -* && ${words[2]} == (start|add) ]]; then
+        local -a projects
+        projects=(${(f)"$(timekeep project list --names 2>/dev/null)"})
+        # Unsorted group keeps projects in most recently used order
+        compadd -V projects -a projects
+        return
+    fi
+    _timekeep_generated "$@"
+}
+
+_timekeep "$@"
+"#;
+
+const FISH_PROJECTS: &str = r#"
+complete -c timekeep -n "__fish_seen_subcommand_from start add; and test (count (commandline -opc)) -eq 2" -f -k -a "(timekeep project list --names 2>/dev/null)"
+"#;
+
+/// Completion script for `shell`, generated from the arguments of `command`.
+pub fn script(shell: Shell, command: &mut Command) -> String {
+    let name = command.get_name().to_owned();
+    let generator = match shell {
+        Shell::Bash => GeneratorShell::Bash,
+        Shell::Zsh => GeneratorShell::Zsh,
+        Shell::Fish => GeneratorShell::Fish,
+    };
+    let mut buffer = Vec::new();
+    generate(generator, command, &name, &mut buffer);
+    let generated = String::from_utf8_lossy(&buffer).into_owned();
+
+    match shell {
+        Shell::Bash => generated + BASH_PROJECTS,
+        Shell::Zsh => {
+            let generated = generated
+                .replace("\n_timekeep() {", "\n_timekeep_generated() {")
+                .replace("\n_timekeep \"$@\"\n", "\n");
+            generated + ZSH_PROJECTS
+        }
+        Shell::Fish => {
+            // Fish completes options per subcommand, so only add projects to those with --project
+            let subcommands: Vec<&str> = command
+                .get_subcommands()
+                .filter(|c| c.get_arguments().any(|a| a.get_long() == Some("project")))
+                .map(|c| c.get_name())
+                .collect();
+            format!(
+                "{}{}complete -c timekeep -n \"__fish_seen_subcommand_from {}\" -l project -s p -x -k -a \"(timekeep project list --names 2>/dev/null)\"\n",
+                generated,
+                FISH_PROJECTS,
+                subcommands.join(" ")
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::{script, Shell};
+    use crate::cli::Cli;
+
+    #[test]
+    fn test_script() {
+        let cases = [
+            (Shell::Bash, "complete -F _timekeep_projects"),
+            (Shell::Zsh, "_timekeep_generated \"$@\""),
+            (
+                Shell::Fish,
+                "from edit view report stats export import\" -l project",
+            ),
+        ];
+        for (shell, hook) in cases {
+            let script = script(shell, &mut Cli::command());
+            assert!(script.contains(hook), "testing: {:?} hook", shell);
+            assert!(
+                script.contains("timekeep project list --names"),
+                "testing: {:?} projects",
+                shell
+            );
+        }
+
+        // The generated zsh function is only called through the wrapper
+        let zsh = script(Shell::Zsh, &mut Cli::command());
+        assert_eq!(zsh.matches("\n_timekeep() {").count(), 1);
+        assert_eq!(zsh.matches("\n_timekeep \"$@\"").count(), 1);
+    }
+}
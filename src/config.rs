@@ -0,0 +1,44 @@
+//! User configuration stored as JSON in the data folder.
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::duration::DurationFormat;
+use crate::hooks::Hooks;
+use crate::ical::CategoryRules;
+use crate::reminders::Reminders;
+use crate::rounding::Rounding;
+use crate::targets::Targets;
+
+/// Settings read from the config file, missing values use their defaults.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Style used for displaying durations.
+    pub duration_format: DurationFormat,
+    /// Rounding applied to durations when displaying tasks.
+    pub rounding: Rounding,
+    /// Daily / weekly working time targets for the balance.
+    pub targets: Targets,
+    /// Rules for choosing projects for events imported from calendars.
+    pub calendar: CategoryRules,
+    /// Reminders given by the daemon.
+    pub reminders: Reminders,
+    /// Commands run before and after tasks change.
+    pub hooks: Hooks,
+}
+
+impl Config {
+    /// Load config from JSON `file`, returns the default config if it doesn't exist.
+    pub fn load(file: &Path) -> Result<Config> {
+        if !file.exists() {
+            return Ok(Config::default());
+        }
+
+        let json = fs::read_to_string(file)?;
+        serde_json::from_str(&json)
+            .map_err(|e| anyhow!("invalid config file {}: {}", file.display(), e))
+    }
+}
@@ -1,90 +1,380 @@
 //! Functionality for reading / writing to the persistent storage database.
+use std::collections::HashMap;
 use std::path::Path;
 
-use crate::tasks::Task;
+use crate::backup::{Backup, BackupTask};
+use crate::importers::ImportedTask;
+use crate::projects::{Project, ProjectPattern};
+use crate::query::TaskQuery;
+use crate::stats::{Statistics, Streak};
+use crate::tasks::{CurrentTask, Task};
 use anyhow::{anyhow, Result};
-use chrono::{DateTime, Utc};
-use rusqlite::{params, Connection, OptionalExtension};
+use chrono::{DateTime, Duration, NaiveDate, Utc, Weekday};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, ToSql, TransactionBehavior};
 
-fn table_exists(file: &Path, table: &str) -> Result<bool> {
-    let connection = Connection::open(&file)?;
+/// Schema migrations, the migration at index `i` upgrades the database from
+/// version `i` (stored in SQLite's `user_version`) to `i + 1`.
+const MIGRATIONS: &[&str] = &[
+    // Databases created before versioning already have the tasks table
+    "CREATE TABLE IF NOT EXISTS tasks (
+        project_name    TEXT NOT NULL,
+        start_time      TEXT NOT NULL,
+        end_time        TEXT NOT NULL,
+        description     TEXT
+    );
+    -- Single row table, the id check stops more than one current task being stored
+    CREATE TABLE IF NOT EXISTS current_task (
+        id              INTEGER PRIMARY KEY CHECK (id = 0),
+        project_name    TEXT NOT NULL,
+        start_time      TEXT NOT NULL,
+        description     TEXT
+    );",
+    "ALTER TABLE tasks ADD COLUMN tags TEXT NOT NULL DEFAULT '';
+    ALTER TABLE current_task ADD COLUMN tags TEXT NOT NULL DEFAULT '';
+    CREATE TABLE projects (
+        name            TEXT PRIMARY KEY,
+        description     TEXT,
+        client          TEXT,
+        rate            REAL,
+        currency        TEXT
+    );
+    CREATE TABLE project_tag_rates (
+        project_name    TEXT NOT NULL REFERENCES projects (name) ON DELETE CASCADE,
+        tag             TEXT NOT NULL,
+        rate            REAL NOT NULL,
+        PRIMARY KEY (project_name, tag)
+    );",
+    // Identifies the record imported tasks came from, to skip it if imported again
+    "ALTER TABLE tasks ADD COLUMN import_key TEXT;
+    CREATE UNIQUE INDEX tasks_import_key ON tasks (import_key);",
+    // Explicit id column so task ids stay the same if the database is vacuumed
+    "CREATE TABLE tasks_with_id (
+        id              INTEGER PRIMARY KEY,
+        project_name    TEXT NOT NULL,
+        start_time      TEXT NOT NULL,
+        end_time        TEXT NOT NULL,
+        description     TEXT,
+        tags            TEXT NOT NULL DEFAULT '',
+        import_key      TEXT
+    );
+    INSERT INTO tasks_with_id (id, project_name, start_time, end_time, description, tags, import_key)
+        SELECT rowid, project_name, start_time, end_time, description, tags, import_key FROM tasks;
+    DROP TABLE tasks;
+    ALTER TABLE tasks_with_id RENAME TO tasks;
+    CREATE UNIQUE INDEX tasks_import_key ON tasks (import_key);",
+];
 
-    let rows: Option<()> = connection
-        .query_row(
-            &format!(
-                "SELECT name FROM sqlite_master WHERE type='table' AND name='{}';",
-                &table
-            ),
-            [],
-            |_| Ok(()),
+/// Columns stored for each task.
+const TASK_COLUMNS: &str = "project_name, start_time, end_time, description, tags";
+/// Columns selected when extracting tasks, in the order `query_tasks` expects.
+const SELECT_TASK_COLUMNS: &str = "project_name, start_time, end_time, description, tags, id";
+
+/// Common table expressions used by the statistics queries, `selected` is the
+/// tasks in the period and `pieces` splits them at each hour (UTC), with times
+/// in seconds since the epoch.
+const STATISTICS_CTE: &str = "WITH RECURSIVE
+    selected AS (
+        SELECT project_name, start_time, end_time FROM tasks
+            WHERE start_time >= ?1 AND start_time < ?2
+            AND (?3 IS NULL OR project_matches(?3, project_name))
+    ),
+    pieces (start, finish, end_time) AS (
+        SELECT start, MIN((start / 3600 + 1) * 3600, end_time), end_time FROM (
+            SELECT CAST(strftime('%s', start_time) AS INTEGER) AS start,
+                CAST(strftime('%s', end_time) AS INTEGER) AS end_time
+            FROM selected
         )
-        .optional()?;
+        UNION ALL
+        SELECT finish, MIN((finish / 3600 + 1) * 3600, end_time), end_time FROM pieces
+            WHERE finish < end_time
+    )";
+
+/// Open connection to database, creating or upgrading the tables if needed.
+fn open_database(file: &Path) -> Result<Connection> {
+    let mut connection = Connection::open(file)?;
+    connection.pragma_update(None, "foreign_keys", true)?;
+    connection.create_scalar_function(
+        "project_matches",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let pattern = ProjectPattern::new(&ctx.get::<String>(0)?);
+            Ok(pattern.matches(&ctx.get::<String>(1)?))
+        },
+    )?;
 
-    match rows {
-        Some(_) => Ok(true),
-        None => Ok(false),
+    // Immediate transaction so concurrent processes don't both apply migrations
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let version: usize = transaction.query_row("PRAGMA user_version;", [], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "database {} was created by a newer version of timekeep",
+            file.display()
+        ));
+    }
+
+    if version < MIGRATIONS.len() {
+        for migration in &MIGRATIONS[version..] {
+            transaction.execute_batch(migration)?;
+        }
+        transaction.pragma_update(None, "user_version", MIGRATIONS.len())?;
     }
+    transaction.commit()?;
+
+    Ok(connection)
 }
 
-fn create_tasks_table(file: &Path) -> Result<()> {
-    let connection = Connection::open(&file)?;
+/// Store tags in a single column, separated by commas.
+fn join_tags(tags: &[String]) -> Result<String> {
+    if let Some(t) = tags.iter().find(|t| t.contains(',')) {
+        return Err(anyhow!("tags cannot contain commas: {:?}", t));
+    }
+
+    Ok(tags.join(","))
+}
 
+fn split_tags(tags: String) -> Vec<String> {
+    tags.split(',')
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_owned())
+        .collect()
+}
+
+fn insert_task(connection: &Connection, task: &Task) -> Result<i64> {
     connection.execute(
-        "CREATE TABLE tasks (
-                project_name    TEXT NOT NULL,
-                start_time      TEXT NOT NULL,
-                end_time        TEXT NOT NULL,
-                description     TEXT
-            );",
-        [],
+        &format!(
+            "INSERT INTO tasks ({}) VALUES (?1, ?2, ?3, ?4, ?5)",
+            TASK_COLUMNS
+        ),
+        params![
+            task.project_name(),
+            task.start_time().to_rfc3339(),
+            task.end_time().to_rfc3339(),
+            task.description(),
+            join_tags(task.tags())?,
+        ],
     )?;
 
+    Ok(connection.last_insert_rowid())
+}
+
+/// Store finished `task`, returns the id of its row.
+pub fn append_task(file: &Path, task: &Task) -> Result<i64> {
+    let connection = open_database(file)?;
+    insert_task(&connection, task)
+}
+
+/// Append imported `tasks`, skipping any whose import key is already stored.
+///
+/// Returns whether each task was new. All tasks are added in a single
+/// transaction, which is rolled back if `dry_run` is true.
+pub fn import_tasks(file: &Path, tasks: &[ImportedTask], dry_run: bool) -> Result<Vec<bool>> {
+    let mut connection = open_database(file)?;
+
+    let transaction = connection.transaction()?;
+    let mut added = Vec::with_capacity(tasks.len());
+    for imported in tasks {
+        let task = &imported.task;
+        let rows = transaction.execute(
+            &format!(
+                "INSERT OR IGNORE INTO tasks ({}, import_key) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                TASK_COLUMNS
+            ),
+            params![
+                task.project_name(),
+                task.start_time().to_rfc3339(),
+                task.end_time().to_rfc3339(),
+                task.description(),
+                join_tags(task.tags())?,
+                imported.key,
+            ],
+        )?;
+        added.push(rows > 0);
+    }
+    if !dry_run {
+        transaction.commit()?;
+    }
+
+    Ok(added)
+}
+
+/// Store `task` as the current running task.
+///
+/// Fails if there is already a current task, unless `overwrite` is true in
+/// which case it's replaced.
+pub fn save_current_task(file: &Path, task: &CurrentTask, overwrite: bool) -> Result<()> {
+    let mut connection = open_database(file)?;
+
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    insert_current_task(&transaction, task, overwrite)?;
+    transaction.commit()?;
+
     Ok(())
 }
 
-pub fn append_task(file: &Path, task: &Task) -> Result<()> {
-    let connection = Connection::open(&file)?;
-    if !table_exists(&file, "tasks")? {
-        create_tasks_table(&file)?;
-    };
+fn insert_current_task(connection: &Connection, task: &CurrentTask, overwrite: bool) -> Result<()> {
+    if overwrite {
+        connection.execute("DELETE FROM current_task WHERE id = 0;", [])?;
+    } else if let Some(current) = query_current_task(connection)? {
+        return Err(anyhow!(
+            "a task is already running ({}), use --overwrite to replace it",
+            current
+        ));
+    }
 
     connection.execute(
-        "INSERT INTO tasks VALUES (?1, ?2, ?3, ?4)",
+        "INSERT INTO current_task (id, project_name, start_time, description, tags)
+            VALUES (0, ?1, ?2, ?3, ?4)",
         params![
             task.project_name(),
             task.start_time().to_rfc3339(),
-            task.end_time().to_rfc3339(),
-            task.description()
+            task.description(),
+            join_tags(task.tags())?,
         ],
     )?;
 
     Ok(())
 }
 
+/// Load the current running task, if there is one.
+pub fn load_current_task(file: &Path) -> Result<Option<CurrentTask>> {
+    let connection = open_database(file)?;
+    query_current_task(&connection)
+}
+
+/// Load the current running task, like [`load_current_task`], using a
+/// read-only connection which doesn't create or upgrade the database.
+///
+/// Meant for frequent reads, e.g. from a shell prompt.
+pub fn peek_current_task(file: &Path) -> Result<Option<CurrentTask>> {
+    if !file.exists() {
+        return Ok(None);
+    }
+    let connection = Connection::open_with_flags(
+        file,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    let version: usize = connection.query_row("PRAGMA user_version;", [], |row| row.get(0))?;
+    if version != MIGRATIONS.len() {
+        // Needs upgrading, or is too new to read
+        drop(connection);
+        return load_current_task(file);
+    }
+
+    query_current_task(&connection)
+}
+
+fn query_current_task(connection: &Connection) -> Result<Option<CurrentTask>> {
+    let row: Option<(String, String, Option<String>, String)> = connection
+        .query_row(
+            "SELECT project_name, start_time, description, tags FROM current_task WHERE id = 0;",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?;
+
+    match row {
+        Some((project_name, start_time, description, tags)) => Ok(Some(
+            CurrentTask::new(
+                project_name,
+                parse_database_datetime(start_time)?,
+                description,
+            )
+            .with_tags(split_tags(tags)),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Remove the current running task and, if `store` is true, append the
+/// finished `task` in the same transaction.
+///
+/// Fails if the current task no longer matches `task`'s project and start
+/// time, i.e. it was ended or replaced since being loaded.
+pub fn finish_current_task(file: &Path, task: &Task, store: bool) -> Result<()> {
+    let mut connection = open_database(file)?;
+
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    remove_current_task(&transaction, task)?;
+    if store {
+        insert_task(&transaction, task)?;
+    }
+    transaction.commit()?;
+
+    Ok(())
+}
+
+/// Store the `ended` current task, if there is one, and store `task` as the
+/// current running task, in a single transaction.
+///
+/// Fails like [`finish_current_task`] if the current task no longer matches
+/// `ended`, and like [`save_current_task`] if another task is still running.
+pub fn switch_current_task(
+    file: &Path,
+    ended: Option<&Task>,
+    task: &CurrentTask,
+    overwrite: bool,
+) -> Result<()> {
+    let mut connection = open_database(file)?;
+
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if let Some(ended) = ended {
+        remove_current_task(&transaction, ended)?;
+        insert_task(&transaction, ended)?;
+    }
+    insert_current_task(&transaction, task, overwrite)?;
+    transaction.commit()?;
+
+    Ok(())
+}
+
+fn remove_current_task(connection: &Connection, task: &Task) -> Result<()> {
+    let removed = connection.execute(
+        "DELETE FROM current_task WHERE id = 0 AND project_name = ?1 AND start_time = ?2;",
+        params![task.project_name(), task.start_time().to_rfc3339()],
+    )?;
+    if removed == 0 {
+        return Err(anyhow!("current task was changed while ending it"));
+    }
+
+    Ok(())
+}
+
 /// Parse datetime string in RFC3339 format and convert to UTC.
 fn parse_database_datetime(s: String) -> Result<DateTime<Utc>> {
     Ok(DateTime::<Utc>::from(DateTime::parse_from_rfc3339(&s)?))
 }
 
-fn extract_tasks_query(file: &Path, query: &str) -> Result<Vec<Task>> {
-    let connection = Connection::open(&file)?;
+/// Parse date string in YYYY-MM-DD format, as returned by SQLite's `date` function.
+fn parse_database_date(s: &str) -> Result<NaiveDate> {
+    Ok(NaiveDate::parse_from_str(s, "%Y-%m-%d")?)
+}
+
+fn extract_tasks_query(file: &Path, query: &str, params: &[&dyn ToSql]) -> Result<Vec<Task>> {
+    let connection = open_database(file)?;
+    query_tasks(&connection, query, params)
+}
 
+fn query_tasks(connection: &Connection, query: &str, params: &[&dyn ToSql]) -> Result<Vec<Task>> {
     let mut stmt = connection.prepare(query)?;
 
     let mut errors = vec![];
     let tasks: Vec<Task> = stmt
-        .query_map([], |row| {
+        .query_map(params, |row| {
             Ok(Task::new(
                 row.get(0)?,
                 parse_database_datetime(row.get(1)?).unwrap(),
                 parse_database_datetime(row.get(2)?).unwrap(),
                 row.get(3)?,
-            ))
+            )
+            .with_tags(split_tags(row.get(4)?))
+            .with_id(row.get(5)?))
         })?
         .filter_map(|x| x.map_err(|e| errors.push(e)).ok())
         .collect();
 
-    if errors.len() > 0 {
+    if !errors.is_empty() {
         return Err(anyhow!(
             "error extracting tasks from database: {:#?}",
             errors
@@ -98,18 +388,813 @@ fn extract_tasks_query(file: &Path, query: &str) -> Result<Vec<Task>> {
 pub fn extract_all_tasks(file: &Path) -> Result<Vec<Task>> {
     extract_tasks_query(
         file,
-        "SELECT project_name, start_time, end_time, description FROM tasks;",
+        &format!("SELECT {} FROM tasks;", SELECT_TASK_COLUMNS),
+        &[],
     )
 }
 
-/// Extract tasks from database with a start time between `from` and `to`.
-pub fn extract_tasks(file: &Path, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Task>> {
-    extract_tasks_query(
-        file, 
+/// Extract the tasks selected by `query` from database.
+pub fn extract_tasks(file: &Path, query: &TaskQuery) -> Result<Vec<Task>> {
+    let (sql, params) = query.to_sql(SELECT_TASK_COLUMNS);
+    let params: Vec<&dyn ToSql> = params.iter().map(|p| p as &dyn ToSql).collect();
+    extract_tasks_query(file, &sql, &params)
+}
+
+/// Calculate statistics for tasks with a start time between `from` and `to`,
+/// optionally only for projects matching `project`.
+pub fn task_statistics(
+    file: &Path,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    project: Option<&ProjectPattern>,
+) -> Result<Statistics> {
+    let connection = open_database(file)?;
+    let params = params![
+        from.to_rfc3339(),
+        to.to_rfc3339(),
+        project.map(|p| p.as_str())
+    ];
+
+    let (task_count, total, average): (usize, i64, f64) = connection.query_row(
+        &format!(
+            "{} SELECT COUNT(*), COALESCE(SUM(seconds), 0), COALESCE(AVG(seconds), 0) FROM (
+                SELECT strftime('%s', end_time) - strftime('%s', start_time) AS seconds
+                FROM selected
+            );",
+            STATISTICS_CTE
+        ),
+        params,
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+
+    let longest = extract_tasks_query(
+        file,
+        &format!(
+            "SELECT {} FROM tasks WHERE start_time >= ?1 and start_time < ?2
+                AND (?3 IS NULL OR project_matches(?3, project_name))
+                ORDER BY julianday(end_time) - julianday(start_time) DESC, start_time
+                LIMIT 1;",
+            SELECT_TASK_COLUMNS,
+        ),
+        &[
+            &from.to_rfc3339(),
+            &to.to_rfc3339(),
+            &project.map(|p| p.as_str()),
+        ],
+    )?
+    .pop();
+
+    // Weekdays numbered from Monday to match chrono
+    let mut by_weekday: Vec<(Weekday, Duration)> = (0..7)
+        .map(|d| Ok((Weekday::try_from(d)?, Duration::zero())))
+        .collect::<Result<_>>()?;
+    let mut stmt = connection.prepare(&format!(
+        "{} SELECT (strftime('%w', start, 'unixepoch') + 6) % 7, SUM(finish - start)
+            FROM pieces GROUP BY 1;",
+        STATISTICS_CTE
+    ))?;
+    for row in stmt.query_map(params, |row| Ok((row.get::<_, usize>(0)?, row.get(1)?)))? {
+        let (weekday, seconds) = row?;
+        by_weekday[weekday].1 = Duration::seconds(seconds);
+    }
+
+    let mut by_hour = vec![Duration::zero(); 24];
+    let mut stmt = connection.prepare(&format!(
+        "{} SELECT CAST(strftime('%H', start, 'unixepoch') AS INTEGER), SUM(finish - start)
+            FROM pieces GROUP BY 1;",
+        STATISTICS_CTE
+    ))?;
+    for row in stmt.query_map(params, |row| Ok((row.get::<_, usize>(0)?, row.get(1)?)))? {
+        let (hour, seconds) = row?;
+        by_hour[hour] = Duration::seconds(seconds);
+    }
+
+    let mut stmt = connection.prepare(&format!(
+        "{} SELECT date(start, 'unixepoch'), SUM(finish - start)
+            FROM pieces GROUP BY 1 HAVING SUM(finish - start) > 0 ORDER BY 1;",
+        STATISTICS_CTE
+    ))?;
+    let by_day = stmt
+        .query_map(params, |row| Ok((row.get(0)?, row.get(1)?)))?
+        .map(|row| {
+            let (day, seconds): (String, i64) = row?;
+            Ok((parse_database_date(&day)?, Duration::seconds(seconds)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // A switch is any task for a different project than the one before it that day
+    let mut stmt = connection.prepare(&format!(
+        "{} SELECT day, SUM(switched) FROM (
+                SELECT date(start_time) AS day, COALESCE(project_name != LAG(project_name)
+                    OVER (PARTITION BY date(start_time) ORDER BY start_time), 0) AS switched
+                FROM selected
+            ) GROUP BY day ORDER BY day;",
+        STATISTICS_CTE
+    ))?;
+    let switches = stmt
+        .query_map(params, |row| Ok((row.get(0)?, row.get(1)?)))?
+        .map(|row| {
+            let (day, count): (String, u32) = row?;
+            Ok((parse_database_date(&day)?, count))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Consecutive days have the same difference between date and row number
+    let mut stmt = connection.prepare(&format!(
+        "{}, days AS (
+                SELECT DISTINCT date(start, 'unixepoch') AS day FROM pieces WHERE finish > start
+            )
+            SELECT MIN(day), MAX(day) FROM (
+                SELECT day, julianday(day) - ROW_NUMBER() OVER (ORDER BY day) AS island
+                FROM days
+            ) GROUP BY island ORDER BY 1;",
+        STATISTICS_CTE
+    ))?;
+    let streaks = stmt
+        .query_map(params, |row| Ok((row.get(0)?, row.get(1)?)))?
+        .map(|row| {
+            let (first, last): (String, String) = row?;
+            Ok(Streak {
+                first: parse_database_date(&first)?,
+                last: parse_database_date(&last)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Statistics {
+        task_count,
+        total: Duration::seconds(total),
+        average: Duration::milliseconds((average * 1000.0).round() as i64),
+        longest,
+        by_weekday,
+        by_hour,
+        by_day,
+        switches,
+        streaks,
+    })
+}
+
+/// Load the task which ended most recently, if any are stored.
+pub fn last_task(file: &Path) -> Result<Option<Task>> {
+    Ok(extract_tasks_query(
+        file,
+        &format!(
+            "SELECT {} FROM tasks ORDER BY end_time DESC, id DESC LIMIT 1;",
+            SELECT_TASK_COLUMNS
+        ),
+        &[],
+    )?
+    .pop())
+}
+
+/// Load the stored task with row `id`, if it exists.
+pub fn load_task(file: &Path, id: i64) -> Result<Option<Task>> {
+    Ok(extract_tasks_query(
+        file,
+        &format!("SELECT {} FROM tasks WHERE id = ?1;", SELECT_TASK_COLUMNS),
+        &[&id],
+    )?
+    .pop())
+}
+
+/// Replace the stored task with row `id` by `task`, fails if it doesn't exist.
+pub fn update_task(file: &Path, id: i64, task: &Task) -> Result<()> {
+    let connection = open_database(file)?;
+
+    let rows = connection.execute(
+        "UPDATE tasks SET project_name = ?1, start_time = ?2, end_time = ?3,
+            description = ?4, tags = ?5 WHERE id = ?6;",
+        params![
+            task.project_name(),
+            task.start_time().to_rfc3339(),
+            task.end_time().to_rfc3339(),
+            task.description(),
+            join_tags(task.tags())?,
+            id,
+        ],
+    )?;
+    if rows == 0 {
+        return Err(anyhow!("no task with id {}", id));
+    }
+
+    Ok(())
+}
+
+/// Delete the stored task with row `id`, fails if it doesn't exist.
+pub fn delete_task(file: &Path, id: i64) -> Result<()> {
+    let connection = open_database(file)?;
+
+    let rows = connection.execute("DELETE FROM tasks WHERE id = ?1;", params![id])?;
+    if rows == 0 {
+        return Err(anyhow!("no task with id {}", id));
+    }
+
+    Ok(())
+}
+
+/// Insert or update `project`, including its tag rates.
+pub fn save_project(file: &Path, project: &Project) -> Result<()> {
+    let mut connection = open_database(file)?;
+
+    let transaction = connection.transaction()?;
+    upsert_project(&transaction, project)?;
+    transaction.commit()?;
+
+    Ok(())
+}
+
+fn upsert_project(connection: &Connection, project: &Project) -> Result<()> {
+    connection.execute(
+        "INSERT INTO projects (name, description, client, rate, currency)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT (name) DO UPDATE SET
+                description = excluded.description,
+                client = excluded.client,
+                rate = excluded.rate,
+                currency = excluded.currency;",
+        params![
+            project.name(),
+            project.description(),
+            project.client(),
+            project.rate(),
+            project.currency(),
+        ],
+    )?;
+
+    connection.execute(
+        "DELETE FROM project_tag_rates WHERE project_name = ?1;",
+        params![project.name()],
+    )?;
+    for (tag, rate) in project.tag_rates() {
+        connection.execute(
+            "INSERT INTO project_tag_rates (project_name, tag, rate) VALUES (?1, ?2, ?3);",
+            params![project.name(), tag, rate],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn extract_projects_query(connection: &Connection, name: Option<&str>) -> Result<Vec<Project>> {
+    let mut stmt = connection.prepare(
+        "SELECT name, description, client, rate, currency FROM projects
+            WHERE ?1 IS NULL OR name = ?1 ORDER BY name;",
+    )?;
+    let mut projects = stmt
+        .query_map(params![name], |row| {
+            let mut project = Project::new(row.get(0)?);
+            project.set_description(row.get(1)?);
+            project.set_client(row.get(2)?);
+            project.set_rate(row.get(3)?);
+            project.set_currency(row.get(4)?);
+            Ok(project)
+        })?
+        .collect::<rusqlite::Result<Vec<Project>>>()?;
+
+    let mut stmt =
+        connection.prepare("SELECT tag, rate FROM project_tag_rates WHERE project_name = ?1;")?;
+    for project in projects.iter_mut() {
+        let rates = stmt
+            .query_map(params![project.name()], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for (tag, rate) in rates {
+            project.set_tag_rate(&tag, Some(rate));
+        }
+    }
+
+    Ok(projects)
+}
+
+/// Load the stored project called `name`, if it exists.
+pub fn load_project(file: &Path, name: &str) -> Result<Option<Project>> {
+    let connection = open_database(file)?;
+    Ok(extract_projects_query(&connection, Some(name))?.pop())
+}
+
+/// Delete the stored details of project `name`, its tasks are kept.
+pub fn delete_project(file: &Path, name: &str) -> Result<()> {
+    let connection = open_database(file)?;
+
+    // Tag rates are deleted by the foreign key
+    let rows = connection.execute("DELETE FROM projects WHERE name = ?1;", params![name])?;
+    if rows == 0 {
+        return Err(anyhow!("no project named {}", name));
+    }
+
+    Ok(())
+}
+
+/// Names of all projects used by tasks or stored as projects, the most
+/// recently used first and then any unused projects by name.
+pub fn recent_projects(file: &Path) -> Result<Vec<String>> {
+    let connection = open_database(file)?;
+
+    let mut stmt = connection.prepare(
+        "SELECT name FROM (
+            SELECT project_name AS name, MAX(start_time) AS last_used FROM (
+                SELECT project_name, start_time FROM tasks
+                UNION ALL SELECT project_name, start_time FROM current_task
+                UNION ALL SELECT name, NULL FROM projects
+            ) GROUP BY project_name
+        ) ORDER BY last_used IS NULL, last_used DESC, name;",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+
+    Ok(names)
+}
+
+/// Extract all stored projects, sorted by name.
+pub fn extract_projects(file: &Path) -> Result<Vec<Project>> {
+    let connection = open_database(file)?;
+    extract_projects_query(&connection, None)
+}
+
+/// Read every task, project and the current task into a backup, in a single
+/// transaction so they're all from the same point in time.
+pub fn create_backup(file: &Path) -> Result<Backup> {
+    let mut connection = open_database(file)?;
+
+    let transaction = connection.transaction()?;
+    let import_keys: HashMap<i64, String> = transaction
+        .prepare("SELECT id, import_key FROM tasks WHERE import_key IS NOT NULL;")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let tasks = query_tasks(
+        &transaction,
         &format!(
-            "SELECT project_name, start_time, end_time, description FROM tasks WHERE start_time >= '{}' and start_time < '{}';",
-            from.to_rfc3339(),
-            to.to_rfc3339(),
+            "SELECT {} FROM tasks ORDER BY start_time, id;",
+            SELECT_TASK_COLUMNS
+        ),
+        &[],
+    )?;
+    let backup = Backup::new(
+        tasks
+            .into_iter()
+            .map(|task| BackupTask {
+                import_key: task.id().and_then(|id| import_keys.get(&id).cloned()),
+                task,
+            })
+            .collect(),
+        extract_projects_query(&transaction, None)?,
+        query_current_task(&transaction)?,
+    );
+    transaction.commit()?;
+
+    Ok(backup)
+}
+
+/// Store everything from `backup` in the database, in a single transaction.
+///
+/// Tasks keep their ids and import keys. Fails if the database already
+/// contains any data, unless `replace` is true in which case the existing
+/// data is deleted first.
+pub fn restore_backup(file: &Path, backup: &Backup, replace: bool) -> Result<()> {
+    let mut connection = open_database(file)?;
+
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if replace {
+        transaction.execute_batch(
+            "DELETE FROM tasks; DELETE FROM current_task;
+            DELETE FROM project_tag_rates; DELETE FROM projects;",
+        )?;
+    } else {
+        let rows: usize = transaction.query_row(
+            "SELECT (SELECT COUNT(*) FROM tasks) + (SELECT COUNT(*) FROM current_task)
+                + (SELECT COUNT(*) FROM projects);",
+            [],
+            |row| row.get(0),
+        )?;
+        if rows > 0 {
+            return Err(anyhow!(
+                "database {} isn't empty, use --force to replace its contents",
+                file.display()
+            ));
+        }
+    }
+
+    for backup_task in &backup.tasks {
+        let task = &backup_task.task;
+        transaction.execute(
+            &format!(
+                "INSERT INTO tasks (id, {}, import_key) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                TASK_COLUMNS
+            ),
+            params![
+                task.id(),
+                task.project_name(),
+                task.start_time().to_rfc3339(),
+                task.end_time().to_rfc3339(),
+                task.description(),
+                join_tags(task.tags())?,
+                backup_task.import_key,
+            ],
+        )?;
+    }
+    for project in &backup.projects {
+        upsert_project(&transaction, project)?;
+    }
+    if let Some(task) = &backup.current_task {
+        insert_current_task(&transaction, task, false)?;
+    }
+    transaction.commit()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use chrono::{Duration, NaiveDate, TimeZone, Utc, Weekday};
+
+    use super::{
+        append_task, create_backup, delete_task, extract_all_tasks, extract_tasks, import_tasks,
+        last_task, load_current_task, load_project, peek_current_task, recent_projects,
+        restore_backup, save_current_task, save_project, task_statistics,
+    };
+    use crate::importers::ImportedTask;
+    use crate::projects::{Project, ProjectPattern};
+    use crate::query::{RangeMatch, SortKey, TaskQuery};
+    use crate::stats::Streak;
+    use crate::tasks::{CurrentTask, Task};
+
+    /// Path for a new, empty, database in the temporary folder.
+    fn temp_database(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("timekeep-{}-{}.db", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_task_statistics() {
+        let file = temp_database("statistics");
+        let tasks = [
+            ("a", (4, 9, 30), (4, 11, 0)),
+            ("b", (4, 11, 0), (4, 12, 0)),
+            ("a", (4, 13, 0), (4, 13, 30)),
+            ("b", (5, 23, 0), (6, 1, 0)),
+            ("a", (8, 10, 0), (8, 10, 15)),
+        ];
+        for (project, (sd, sh, sm), (ed, eh, em)) in tasks {
+            let task = Task::new(
+                project.to_owned(),
+                Utc.with_ymd_and_hms(2022, 7, sd, sh, sm, 0).unwrap(),
+                Utc.with_ymd_and_hms(2022, 7, ed, eh, em, 0).unwrap(),
+                None,
+            );
+            append_task(&file, &task).unwrap();
+        }
+
+        let stats = task_statistics(
+            &file,
+            Utc.with_ymd_and_hms(2022, 7, 1, 0, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2022, 8, 1, 0, 0, 0).unwrap(),
+            None,
         )
-    )
+        .unwrap();
+        fs::remove_file(&file).unwrap();
+
+        let date = |day| NaiveDate::from_ymd_opt(2022, 7, day).unwrap();
+        assert_eq!(stats.task_count, 5);
+        assert_eq!(stats.total, Duration::minutes(315));
+        assert_eq!(stats.average, Duration::minutes(63));
+        assert_eq!(stats.longest.unwrap().duration(), Duration::hours(2));
+
+        let weekdays: Vec<(Weekday, i64)> = stats
+            .by_weekday
+            .iter()
+            .map(|(d, t)| (*d, t.num_minutes()))
+            .collect();
+        assert_eq!(
+            weekdays,
+            vec![
+                (Weekday::Mon, 180),
+                (Weekday::Tue, 60),
+                (Weekday::Wed, 60),
+                (Weekday::Thu, 0),
+                (Weekday::Fri, 15),
+                (Weekday::Sat, 0),
+                (Weekday::Sun, 0),
+            ]
+        );
+
+        let hours: Vec<(usize, i64)> = stats
+            .by_hour
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| !t.is_zero())
+            .map(|(h, t)| (h, t.num_minutes()))
+            .collect();
+        assert_eq!(
+            hours,
+            vec![(0, 60), (9, 30), (10, 75), (11, 60), (13, 30), (23, 60)]
+        );
+
+        let days: Vec<(NaiveDate, i64)> = stats
+            .by_day
+            .iter()
+            .map(|(d, t)| (*d, t.num_minutes()))
+            .collect();
+        assert_eq!(
+            days,
+            vec![(date(4), 180), (date(5), 60), (date(6), 60), (date(8), 15)]
+        );
+        assert_eq!(
+            stats.switches,
+            vec![(date(4), 2), (date(5), 0), (date(8), 0)]
+        );
+        assert_eq!(
+            stats.streaks,
+            vec![
+                Streak {
+                    first: date(4),
+                    last: date(6)
+                },
+                Streak {
+                    first: date(8),
+                    last: date(8)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_backup_round_trip() {
+        let file = temp_database("backup");
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2022, 7, 1, 10, 0, 0).unwrap();
+        let task = Task::new("acme".to_owned(), start, end, Some("work".to_owned()))
+            .with_tags(vec!["a".to_owned(), "b".to_owned()]);
+        let mut project = Project::new("acme".to_owned());
+        project.set_tag_rate("a", Some(10.0));
+        append_task(&file, &task).unwrap();
+        save_project(&file, &project).unwrap();
+        save_current_task(
+            &file,
+            &CurrentTask::new("other".to_owned(), end, None),
+            false,
+        )
+        .unwrap();
+
+        let backup = create_backup(&file).unwrap();
+        assert!(restore_backup(&file, &backup, false).is_err());
+
+        let restored = temp_database("restored");
+        restore_backup(&restored, &backup, false).unwrap();
+        // Replacing doesn't duplicate the existing data
+        restore_backup(&restored, &backup, true).unwrap();
+
+        let tasks = extract_all_tasks(&restored).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].project_name(), "acme");
+        assert_eq!(tasks[0].description(), Some("work"));
+        assert_eq!(tasks[0].tags(), ["a", "b"]);
+        assert_eq!(tasks[0].start_time(), &start);
+        assert_eq!(load_project(&restored, "acme").unwrap(), Some(project));
+        let current = load_current_task(&restored).unwrap().unwrap();
+        assert_eq!(current.project_name(), "other");
+
+        fs::remove_file(&file).unwrap();
+        fs::remove_file(&restored).unwrap();
+    }
+
+    #[test]
+    fn test_backup_keeps_imports() {
+        let file = temp_database("backup-imports");
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap();
+        let imported: Vec<ImportedTask> = ["a", "b"]
+            .iter()
+            .map(|key| ImportedTask {
+                key: key.to_string(),
+                task: Task::new("acme".to_owned(), start, start + Duration::hours(1), None),
+            })
+            .collect();
+        import_tasks(&file, &imported[..1], false).unwrap();
+        // Delete a task so the ids have a gap, which must be kept
+        let task = Task::new("acme".to_owned(), start, start, None);
+        let deleted = append_task(&file, &task).unwrap();
+        append_task(&file, &task).unwrap();
+        delete_task(&file, deleted).unwrap();
+        let ids: Vec<Option<i64>> = extract_all_tasks(&file)
+            .unwrap()
+            .iter()
+            .map(|t| t.id())
+            .collect();
+
+        let restored = temp_database("restored-imports");
+        restore_backup(&restored, &create_backup(&file).unwrap(), false).unwrap();
+        let restored_ids: Vec<Option<i64>> = extract_all_tasks(&restored)
+            .unwrap()
+            .iter()
+            .map(|t| t.id())
+            .collect();
+        assert_eq!(restored_ids, vec![Some(1), Some(3)]);
+        assert_eq!(restored_ids, ids);
+
+        // Only the task which wasn't imported before is added
+        assert_eq!(
+            import_tasks(&restored, &imported, false).unwrap(),
+            vec![false, true]
+        );
+        assert_eq!(extract_all_tasks(&restored).unwrap().len(), 3);
+
+        fs::remove_file(&file).unwrap();
+        fs::remove_file(&restored).unwrap();
+    }
+
+    #[test]
+    fn test_peek_current_task() {
+        let file = temp_database("peek");
+        assert!(peek_current_task(&file).unwrap().is_none());
+        assert!(!file.exists(), "database isn't created when peeking");
+
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap();
+        let task = CurrentTask::new("acme".to_owned(), start, None);
+        save_current_task(&file, &task, false).unwrap();
+        let current = peek_current_task(&file).unwrap().unwrap();
+        assert_eq!(current.project_name(), "acme");
+        assert_eq!(current.start_time(), &start);
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_import_tasks() {
+        let file = temp_database("import");
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap();
+        let imported: Vec<ImportedTask> = ["a", "b", "a"]
+            .iter()
+            .map(|key| ImportedTask {
+                key: key.to_string(),
+                task: Task::new("acme".to_owned(), start, start + Duration::hours(1), None),
+            })
+            .collect();
+
+        assert_eq!(
+            import_tasks(&file, &imported, true).unwrap(),
+            vec![true, true, false]
+        );
+        assert!(extract_all_tasks(&file).unwrap().is_empty());
+
+        assert_eq!(
+            import_tasks(&file, &imported[..2], false).unwrap(),
+            vec![true, true]
+        );
+        assert_eq!(
+            import_tasks(&file, &imported, false).unwrap(),
+            vec![false, false, false]
+        );
+        assert_eq!(extract_all_tasks(&file).unwrap().len(), 2);
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_recent_projects() {
+        let file = temp_database("recent");
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap();
+        for (project, hours) in [("old", 0), ("new", 5), ("middle", 2), ("old", 1)] {
+            let start = start + Duration::hours(hours);
+            let task = Task::new(project.to_owned(), start, start, None);
+            append_task(&file, &task).unwrap();
+        }
+        save_project(&file, &Project::new("unused".to_owned())).unwrap();
+        save_project(&file, &Project::new("middle".to_owned())).unwrap();
+        let current = CurrentTask::new("current".to_owned(), start + Duration::hours(9), None);
+        save_current_task(&file, &current, false).unwrap();
+
+        assert_eq!(
+            recent_projects(&file).unwrap(),
+            vec!["current", "new", "middle", "old", "unused"]
+        );
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_last_task() {
+        let file = temp_database("last");
+        assert!(last_task(&file).unwrap().is_none());
+
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap();
+        for (project, hours) in [("long", 8), ("short", 1), ("empty", 0)] {
+            let task = Task::new(
+                project.to_owned(),
+                start,
+                start + Duration::hours(hours),
+                None,
+            );
+            append_task(&file, &task).unwrap();
+        }
+
+        assert_eq!(last_task(&file).unwrap().unwrap().project_name(), "long");
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_extract_tasks() {
+        let file = temp_database("query");
+        let day = |h, m| Utc.with_ymd_and_hms(2022, 7, 1, h, m, 0).unwrap();
+        let tasks = [
+            ("acme/web", (8, 30), (9, 30), "Fix login", "bug"),
+            ("acme/api", (9, 30), (11, 0), "100% coverage", "bug,urgent"),
+            ("internal", (11, 0), (11, 15), "standup", ""),
+            ("acme/web", (13, 0), (13, 45), "fix_layout", "urgent"),
+        ];
+        for (project, start, end, description, tags) in tasks {
+            let task = Task::new(
+                project.to_owned(),
+                day(start.0, start.1),
+                day(end.0, end.1),
+                Some(description.to_owned()),
+            )
+            .with_tags(
+                tags.split(',')
+                    .filter(|t| !t.is_empty())
+                    .map(|t| t.to_owned())
+                    .collect(),
+            );
+            append_task(&file, &task).unwrap();
+        }
+
+        let cases = [
+            (
+                TaskQuery::new(),
+                vec!["Fix login", "100% coverage", "standup", "fix_layout"],
+            ),
+            (
+                TaskQuery::new().range(day(9, 0), day(11, 0)),
+                vec!["100% coverage"],
+            ),
+            (
+                TaskQuery::new()
+                    .range(day(9, 0), day(11, 0))
+                    .range_match(RangeMatch::End),
+                vec!["Fix login"],
+            ),
+            // Ending at the start of the range is included, at its end isn't
+            (
+                TaskQuery::new()
+                    .range(day(9, 30), day(11, 15))
+                    .range_match(RangeMatch::End),
+                vec!["Fix login", "100% coverage"],
+            ),
+            (
+                TaskQuery::new()
+                    .range(day(9, 0), day(11, 0))
+                    .range_match(RangeMatch::Overlap),
+                vec!["Fix login", "100% coverage"],
+            ),
+            (
+                TaskQuery::new()
+                    .project(ProjectPattern::new("acme/web"))
+                    .project(ProjectPattern::new("internal")),
+                vec!["Fix login", "standup", "fix_layout"],
+            ),
+            (
+                TaskQuery::new().tag("bug"),
+                vec!["Fix login", "100% coverage"],
+            ),
+            (
+                TaskQuery::new().tag("bug").tag("urgent"),
+                vec!["100% coverage"],
+            ),
+            (TaskQuery::new().tag("urg"), vec![]),
+            (
+                TaskQuery::new().search("FIX"),
+                vec!["Fix login", "fix_layout"],
+            ),
+            // Wildcards in the search text are matched literally
+            (TaskQuery::new().search("%"), vec!["100% coverage"]),
+            (TaskQuery::new().search("x_l"), vec!["fix_layout"]),
+            (
+                TaskQuery::new()
+                    .sort_by(SortKey::Duration)
+                    .descending(true)
+                    .limit(2),
+                vec!["100% coverage", "Fix login"],
+            ),
+            (
+                TaskQuery::new()
+                    .sort_by(SortKey::Project)
+                    .offset(1)
+                    .limit(2),
+                vec!["Fix login", "fix_layout"],
+            ),
+            (TaskQuery::new().offset(3), vec!["fix_layout"]),
+        ];
+        for (query, expected) in cases {
+            let tasks = extract_tasks(&file, &query).unwrap();
+            let descriptions: Vec<&str> = tasks.iter().filter_map(|t| t.description()).collect();
+            assert_eq!(descriptions, expected, "testing: {:?}", query);
+        }
+
+        fs::remove_file(&file).unwrap();
+    }
 }
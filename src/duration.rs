@@ -0,0 +1,183 @@
+//! Formatting of durations for display.
+use chrono::Duration;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Divides two integers and rounds result towards nearest integer.
+///
+/// Values ending in .5 are rounded up to the larger integer.
+fn rounded_div(numerator: i64, denominator: i64) -> i64 {
+    (numerator + (denominator / 2)) / denominator
+}
+
+/// Split positive duration into whole hours and minutes, rounded to the nearest minute.
+fn hours_minutes(d: Duration) -> (i64, i64) {
+    let minutes = rounded_div(d.num_seconds(), 60);
+    (minutes / 60, minutes % 60)
+}
+
+/// Format duration as a human readable string, e.g. "1 hr 19 min".
+pub fn human_duration(d: Duration) -> String {
+    if d < Duration::zero() {
+        return format!("-{}", human_duration(-d));
+    }
+
+    let milli = d.num_milliseconds();
+    if milli < 1000 {
+        return format!("{} ms", milli);
+    };
+
+    let seconds = rounded_div(milli, 1000);
+    if seconds < 60 {
+        return format!("{} s", seconds);
+    };
+
+    if seconds < 60 * 60 {
+        return format!("{} min {} s", seconds / 60, seconds % 60);
+    };
+
+    let minutes = rounded_div(seconds, 60);
+    if minutes < 24 * 60 {
+        return format!("{} hr {} min", minutes / 60, minutes % 60);
+    };
+
+    let hours = rounded_div(minutes, 60);
+    format!("{} day {} hr", hours / 24, hours % 24)
+}
+
+/// Style used for formatting durations.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DurationFormat {
+    /// Human readable, e.g. "1 hr 19 min"
+    #[default]
+    Human,
+    /// Hours and minutes, e.g. "01:19"
+    Clock,
+    /// Decimal hours to 2 decimal places, e.g. "1.32"
+    Decimal,
+    /// ISO 8601 duration, e.g. "PT1H19M"
+    Iso,
+    /// Compact hours and minutes, e.g. "1h19m"
+    Compact,
+}
+
+impl DurationFormat {
+    /// Format `d` in this style, negative durations start with "-".
+    ///
+    /// Except for human and decimal, durations of a minute or more are
+    /// rounded to the nearest minute.
+    pub fn format(&self, d: Duration) -> String {
+        if d < Duration::zero() {
+            return format!("-{}", self.format(-d));
+        }
+
+        match self {
+            DurationFormat::Human => human_duration(d),
+            DurationFormat::Clock => {
+                let (h, m) = hours_minutes(d);
+                format!("{:02}:{:02}", h, m)
+            }
+            DurationFormat::Decimal => {
+                format!("{:.2}", d.num_milliseconds() as f64 / 3_600_000.0)
+            }
+            DurationFormat::Iso => match hours_minutes(d) {
+                (0, 0) => format!("PT{}S", rounded_div(d.num_milliseconds(), 1000)),
+                (0, m) => format!("PT{}M", m),
+                (h, 0) => format!("PT{}H", h),
+                (h, m) => format!("PT{}H{}M", h, m),
+            },
+            DurationFormat::Compact => match hours_minutes(d) {
+                (0, 0) => format!("{}s", rounded_div(d.num_milliseconds(), 1000)),
+                (0, m) => format!("{}m", m),
+                (h, m) => format!("{}h{}m", h, m),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::{human_duration, rounded_div, DurationFormat};
+
+    #[test]
+    fn test_rounded_div() {
+        let tests = [
+            (3, 2, 2),   // 3 / 2 = 1.5    -> 2
+            (2, 3, 1),   // 2 / 3 = 0.66   -> 1
+            (1, 2, 1),   // 1 / 2 = 0.5    -> 1
+            (1, 3, 0),   // 1 / 3 = 0.33   -> 0
+            (-3, 2, -1), // -3 / 2 = -1.5  -> -1
+            (-2, -3, 1), // -2 / -3 = 0.66 -> 1
+            (1, -2, 0),  // 1 / -2 = -0.5  -> 0
+            (-1, 3, 0),  // -1 / 3 = 0.33  -> 0
+        ];
+
+        for (n, d, a) in tests {
+            assert_eq!(
+                rounded_div(n, d),
+                a,
+                "testing: rounded_div({}, {}) == {}",
+                n,
+                d,
+                a
+            );
+        }
+    }
+
+    #[test]
+    fn test_human_duration() {
+        let durations = [
+            (Duration::milliseconds(947), "947 ms"),
+            (Duration::milliseconds(1947), "2 s"),
+            (Duration::seconds(57), "57 s"),
+            (Duration::seconds(157), "2 min 37 s"),
+            (Duration::seconds(4734), "1 hr 19 min"),
+            (Duration::seconds(7199), "2 hr 0 min"),
+            (Duration::seconds(92750), "1 day 2 hr"),
+            (Duration::hours(173), "7 day 5 hr"),
+            (Duration::seconds(-4734), "-1 hr 19 min"),
+            (Duration::milliseconds(-947), "-947 ms"),
+        ];
+
+        for (d, a) in durations {
+            assert_eq!(
+                human_duration(d),
+                a,
+                "testing: human_duration({}) == {}",
+                d,
+                a
+            );
+        }
+    }
+
+    #[test]
+    fn test_duration_formats() {
+        let tests = [
+            (DurationFormat::Clock, 4734, "01:19"),
+            (DurationFormat::Clock, 173 * 3600, "173:00"),
+            (DurationFormat::Decimal, 4734, "1.31"),
+            (DurationFormat::Decimal, 4752, "1.32"),
+            (DurationFormat::Iso, 4734, "PT1H19M"),
+            (DurationFormat::Iso, 7200, "PT2H"),
+            (DurationFormat::Iso, 29, "PT29S"),
+            (DurationFormat::Compact, 4734, "1h19m"),
+            (DurationFormat::Compact, 157, "3m"),
+            (DurationFormat::Compact, -4734, "-1h19m"),
+            (DurationFormat::Clock, -90, "-00:02"),
+        ];
+
+        for (format, seconds, a) in tests {
+            assert_eq!(
+                format.format(Duration::seconds(seconds)),
+                a,
+                "testing: {:?} format of {} s == {}",
+                format,
+                seconds,
+                a
+            );
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Fuzzy matching of text, e.g. for searching project names.
+
+/// Bonus for each matched character, to prefer more of the query matching.
+const MATCH_SCORE: i64 = 16;
+/// Bonus for a match directly after the previous matched character.
+const CONSECUTIVE_BONUS: i64 = 12;
+/// Bonus for a match at the start of the text or of a word / project level.
+const WORD_START_BONUS: i64 = 10;
+/// Penalty for each character skipped between matches.
+const GAP_PENALTY: i64 = 1;
+
+/// Score how well `query` matches `text`, ignoring case, or `None` if the
+/// characters of `query` don't all appear in order in `text`.
+///
+/// Higher scores are better matches, an empty query matches everything with 0.
+pub fn score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().flat_map(|c| c.to_lowercase()).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+
+    for q in query.chars().flat_map(|c| c.to_lowercase()) {
+        let found = position + text[position..].iter().position(|c| *c == q)?;
+
+        score += MATCH_SCORE;
+        match previous {
+            Some(p) if found == p + 1 => score += CONSECUTIVE_BONUS,
+            Some(p) => score -= GAP_PENALTY * (found - p - 1) as i64,
+            None => score -= GAP_PENALTY * found as i64,
+        }
+        if found == 0 || matches!(text[found - 1], ' ' | '/' | '-' | '_' | '.') {
+            score += WORD_START_BONUS;
+        }
+
+        previous = Some(found);
+        position = found + 1;
+    }
+
+    Some(score)
+}
+
+/// Items matching `query`, best matches first, items with the same score stay
+/// in their original order.
+pub fn filter<'a, T: AsRef<str>>(query: &str, items: &'a [T]) -> Vec<&'a T> {
+    let mut matches: Vec<(i64, &T)> = items
+        .iter()
+        .filter_map(|i| score(query, i.as_ref()).map(|s| (s, i)))
+        .collect();
+    // Stable sort keeps the original order for equal scores
+    matches.sort_by_key(|(s, _)| -s);
+
+    matches.into_iter().map(|(_, i)| i).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{filter, score};
+
+    #[test]
+    fn test_score() {
+        let cases = [
+            ("", "anything", true),
+            ("web", "acme/website", true),
+            ("AW", "acme/web", true),
+            ("wa", "acme/web", false),
+            ("xyz", "acme", false),
+        ];
+        for (query, text, matches) in cases {
+            assert_eq!(
+                score(query, text).is_some(),
+                matches,
+                "testing: score({:?}, {:?})",
+                query,
+                text
+            );
+        }
+
+        // Consecutive and word start matches are better than scattered ones
+        assert!(score("web", "web") > score("web", "wxexb"));
+        assert!(score("aw", "acme/web") > score("aw", "drawing"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let projects = ["internal", "acme/web", "acme/api", "webinar"];
+        assert_eq!(filter("web", &projects), vec![&"webinar", &"acme/web"]);
+        assert_eq!(filter("", &projects), projects.iter().collect::<Vec<_>>());
+        assert!(filter("zzz", &projects).is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+//! User commands run before and after tasks change.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Change to the tasks which hooks can be run for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Start,
+    End,
+    Add,
+    Edit,
+    Delete,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Action::Start => "start",
+            Action::End => "end",
+            Action::Add => "add",
+            Action::Edit => "edit",
+            Action::Delete => "delete",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Shell commands for each action, run with the task as JSON on stdin and
+/// the hook's name, e.g. `pre-start`, in `TIMEKEEP_HOOK`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    /// Run before an action, which is cancelled if the command fails.
+    pub pre: BTreeMap<Action, String>,
+    /// Run after an action.
+    pub post: BTreeMap<Action, String>,
+}
+
+impl Hooks {
+    /// Run the pre-hook for `action`, fails if the hook does so the action
+    /// should be cancelled.
+    pub fn pre<T: Serialize>(&self, action: Action, task: &T) -> Result<()> {
+        match self.pre.get(&action) {
+            Some(command) => run(command, &format!("pre-{}", action), task)
+                .map_err(|e| anyhow!("{} cancelled by pre-{} hook: {}", action, action, e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Run the post-hook for `action`, failures are only printed as the
+    /// action has already happened.
+    pub fn post<T: Serialize>(&self, action: Action, task: &T) {
+        if let Some(command) = self.post.get(&action) {
+            if let Err(e) = run(command, &format!("post-{}", action), task) {
+                eprintln!("Warning: post-{} hook failed: {}", action, e);
+            }
+        }
+    }
+}
+
+fn run<T: Serialize>(command: &str, hook: &str, task: &T) -> Result<()> {
+    let json = serde_json::to_string(task)?;
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .env("TIMEKEEP_HOOK", hook)
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // Hooks which don't read the task close stdin early, that's not an error
+        let _ = stdin.write_all(json.as_bytes());
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("`{}` exited with {}", command, status));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::{Action, Hooks};
+    use crate::Task;
+
+    #[test]
+    fn test_hooks() {
+        let output = env::temp_dir().join(format!("timekeep-test-hooks-{}", std::process::id()));
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap();
+        let task = Task::new("acme".to_owned(), start, start, None).with_id(Some(3));
+
+        let json = format!(
+            r#"{{
+                "pre": {{ "add": "cat > {0}", "delete": "exit 1" }},
+                "post": {{ "add": "echo $TIMEKEEP_HOOK >> {0}", "edit": "exit 1" }}
+            }}"#,
+            output.display()
+        );
+        let hooks: Hooks = serde_json::from_str(&json).unwrap();
+
+        hooks.pre(Action::Add, &task).unwrap();
+        hooks.post(Action::Add, &task);
+        let written = fs::read_to_string(&output).unwrap();
+        let (stdin, hook) = written.split_once('}').unwrap();
+        let stored: Task = serde_json::from_str(&format!("{}}}", stdin)).unwrap();
+        assert_eq!(stored.id(), Some(3));
+        assert_eq!(stored.project_name(), "acme");
+        assert_eq!(hook, "post-add\n");
+
+        let cases = [
+            (Action::Start, true),
+            (Action::Delete, false),
+            // Only post-hooks are given for edit
+            (Action::Edit, true),
+        ];
+        for (action, allowed) in cases {
+            assert_eq!(
+                hooks.pre(action, &task).is_ok(),
+                allowed,
+                "testing: pre-{} hook",
+                action
+            );
+        }
+        // Failing post-hooks are only a warning
+        hooks.post(Action::Edit, &task);
+
+        assert!(serde_json::from_str::<Hooks>(r#"{"pre": {"stop": "true"}}"#).is_err());
+        fs::remove_file(&output).unwrap();
+    }
+}
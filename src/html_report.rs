@@ -0,0 +1,393 @@
+//! Rendering of the project report as a standalone HTML page with SVG charts.
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+use chrono::{DateTime, Duration, NaiveDate, Timelike, Utc};
+
+use crate::duration::DurationFormat;
+use crate::invoice::escape_html;
+use crate::report::{self, format_amount, ProjectSummary, RollUp};
+use crate::table::project_colour_index;
+use crate::tasks::Task;
+
+/// Chart colours in the same order as the terminal's project colours, so
+/// projects look the same in both.
+const PROJECT_FILLS: [&str; 10] = [
+    "#cc3333", "#2e8b57", "#b8860b", "#3366cc", "#aa44aa", "#2a9d8f", "#e76f51", "#6ab04c",
+    "#5dade2", "#d980fa",
+];
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; text-align: left; }
+td.number, th.number { text-align: right; }
+svg text { font-size: 12px; fill: #222; }";
+/// Width of SVG charts, and of the label area to the left of bars / timelines.
+const CHART_WIDTH: f64 = 720.0;
+const LABEL_WIDTH: f64 = 160.0;
+const ROW_HEIGHT: f64 = 22.0;
+const PIE_RADIUS: f64 = 100.0;
+
+fn project_fill(project: &str) -> &'static str {
+    PROJECT_FILLS[project_colour_index(project)]
+}
+
+/// Fraction of `total` which `d` is, 0 if `total` is zero.
+fn fraction(d: Duration, total: Duration) -> f64 {
+    if total <= Duration::zero() {
+        return 0.0;
+    }
+    d.num_milliseconds() as f64 / total.num_milliseconds() as f64
+}
+
+/// Pie chart with a slice for each project, with a legend.
+fn pie_chart(rows: &[(&str, Duration)]) -> String {
+    let total = rows.iter().fold(Duration::zero(), |acc, r| acc + r.1);
+    let size = PIE_RADIUS * 2.0;
+    let height = size.max(rows.len() as f64 * ROW_HEIGHT);
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+        w = CHART_WIDTH,
+        h = height
+    )
+    .unwrap();
+
+    let (cx, cy) = (PIE_RADIUS, PIE_RADIUS);
+    let mut angle = 0.0;
+    for (i, (project, duration)) in rows.iter().enumerate() {
+        let share = fraction(*duration, total);
+        let title = format!("{} ({:.0}%)", escape_html(project), share * 100.0);
+
+        if share >= 1.0 {
+            writeln!(
+                out,
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"><title>{}</title></circle>",
+                cx,
+                cy,
+                PIE_RADIUS,
+                project_fill(project),
+                title
+            )
+            .unwrap();
+        } else if share > 0.0 {
+            // Angles start at the top and go clockwise
+            let end = angle + share * 2.0 * PI;
+            let point = |a: f64| (cx + PIE_RADIUS * a.sin(), cy - PIE_RADIUS * a.cos());
+            let (x1, y1) = point(angle);
+            let (x2, y2) = point(end);
+            writeln!(
+                out,
+                "<path d=\"M {:.2} {:.2} L {:.2} {:.2} A {r} {r} 0 {} 1 {:.2} {:.2} Z\" fill=\"{}\"><title>{}</title></path>",
+                cx,
+                cy,
+                x1,
+                y1,
+                u8::from(share > 0.5),
+                x2,
+                y2,
+                project_fill(project),
+                title,
+                r = PIE_RADIUS,
+            )
+            .unwrap();
+            angle = end;
+        }
+
+        let y = i as f64 * ROW_HEIGHT;
+        writeln!(
+            out,
+            "<rect x=\"{}\" y=\"{}\" width=\"14\" height=\"14\" fill=\"{}\"/><text x=\"{}\" y=\"{}\">{}</text>",
+            size + 40.0,
+            y + 2.0,
+            project_fill(project),
+            size + 60.0,
+            y + 14.0,
+            title
+        )
+        .unwrap();
+    }
+    writeln!(out, "</svg>").unwrap();
+
+    out
+}
+
+/// Horizontal bar chart of the rolled up time for each project.
+fn bar_chart(rows: &[RollUp], format: DurationFormat) -> String {
+    let max = rows
+        .iter()
+        .map(|r| r.duration)
+        .max()
+        .unwrap_or_else(Duration::zero);
+    // Leave space after the bars for the duration text
+    let bar_width = CHART_WIDTH - LABEL_WIDTH - 100.0;
+    let height = rows.len() as f64 * ROW_HEIGHT;
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+        w = CHART_WIDTH,
+        h = height
+    )
+    .unwrap();
+    for (i, r) in rows.iter().enumerate() {
+        let y = i as f64 * ROW_HEIGHT;
+        let width = fraction(r.duration, max) * bar_width;
+        writeln!(
+            out,
+            "<text x=\"{}\" y=\"{}\">{}</text>",
+            r.depth as f64 * 12.0,
+            y + 15.0,
+            escape_html(&r.project)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "<rect x=\"{}\" y=\"{}\" width=\"{:.2}\" height=\"{}\" fill=\"{}\"/>",
+            LABEL_WIDTH,
+            y + 3.0,
+            width,
+            ROW_HEIGHT - 6.0,
+            project_fill(&r.project)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "<text x=\"{:.2}\" y=\"{}\">{}</text>",
+            LABEL_WIDTH + width + 6.0,
+            y + 15.0,
+            escape_html(&format.format(r.duration))
+        )
+        .unwrap();
+    }
+    writeln!(out, "</svg>").unwrap();
+
+    out
+}
+
+/// Position of `time` across the day, from 0 at midnight to 1 at the next midnight.
+fn day_fraction(time: &DateTime<Utc>, day: NaiveDate) -> f64 {
+    if time.date_naive() > day {
+        return 1.0;
+    }
+    f64::from(time.num_seconds_from_midnight()) / (24.0 * 60.0 * 60.0)
+}
+
+/// Timeline with a row for each day, showing when each task was worked on.
+fn timeline(days: &BTreeMap<NaiveDate, Vec<Task>>, format: DurationFormat) -> String {
+    let width = CHART_WIDTH - LABEL_WIDTH;
+    // Extra row at the top for the hour labels
+    let height = (days.len() + 1) as f64 * ROW_HEIGHT;
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+        w = CHART_WIDTH,
+        h = height
+    )
+    .unwrap();
+    for hour in (0..=24).step_by(3) {
+        let x = LABEL_WIDTH + width * f64::from(hour) / 24.0;
+        writeln!(
+            out,
+            "<line x1=\"{x:.2}\" y1=\"{}\" x2=\"{x:.2}\" y2=\"{}\" stroke=\"#ddd\"/><text x=\"{x:.2}\" y=\"14\" text-anchor=\"middle\">{:02}:00</text>",
+            ROW_HEIGHT,
+            height,
+            hour,
+        )
+        .unwrap();
+    }
+
+    for (i, (day, tasks)) in days.iter().enumerate() {
+        let y = (i + 1) as f64 * ROW_HEIGHT;
+        writeln!(
+            out,
+            "<text x=\"0\" y=\"{}\">{}</text>",
+            y + 15.0,
+            day.format("%a %Y-%m-%d")
+        )
+        .unwrap();
+
+        for t in tasks {
+            let start = day_fraction(t.start_time(), *day);
+            let end = day_fraction(t.end_time(), *day);
+            writeln!(
+                out,
+                "<rect x=\"{:.2}\" y=\"{}\" width=\"{:.2}\" height=\"{}\" fill=\"{}\"><title>{}</title></rect>",
+                LABEL_WIDTH + start * width,
+                y + 3.0,
+                (end - start) * width,
+                ROW_HEIGHT - 6.0,
+                project_fill(t.project_name()),
+                escape_html(&t.summary(format))
+            )
+            .unwrap();
+        }
+    }
+    writeln!(out, "</svg>").unwrap();
+
+    out
+}
+
+/// Render the report for `summaries` between `from` and `to` as a HTML page,
+/// with a timeline of the tasks in `days` (see `report::group_by_day`).
+///
+/// Styles and charts are included in the page, so it can be shared as a single file.
+pub fn render_html_report(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    summaries: &[ProjectSummary],
+    days: &BTreeMap<NaiveDate, Vec<Task>>,
+    format: DurationFormat,
+) -> String {
+    // End time is exclusive so show the last day included
+    let title = format!(
+        "Time report {} to {}",
+        from.naive_local().format("%Y-%m-%d"),
+        (to - Duration::seconds(1)).naive_local().format("%Y-%m-%d")
+    );
+    let roll_up = report::roll_up(summaries);
+    let total = summaries
+        .iter()
+        .fold(Duration::zero(), |acc, s| acc + s.duration);
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "<title>{}</title>\n<style>\n{}\n</style>",
+        title, STYLE
+    )
+    .unwrap();
+    writeln!(out, "</head>\n<body>\n<h1>{}</h1>", title).unwrap();
+
+    writeln!(out, "<h2>Projects</h2>\n<table>").unwrap();
+    writeln!(
+        out,
+        "<tr><th>Project</th><th>Duration</th><th class=\"number\">Amount</th></tr>"
+    )
+    .unwrap();
+    for r in &roll_up {
+        let amounts: Vec<String> = r
+            .amounts
+            .iter()
+            .map(|(c, a)| format_amount(*a, Some(c)))
+            .collect();
+        writeln!(
+            out,
+            "<tr><td style=\"padding-left: {}em\">{}</td><td>{}</td><td class=\"number\">{}</td></tr>",
+            0.8 + r.depth as f64 * 1.5,
+            escape_html(&r.project),
+            escape_html(&format.format(r.duration)),
+            escape_html(&amounts.join(", "))
+        )
+        .unwrap();
+    }
+    let totals: Vec<String> = report::currency_totals(summaries)
+        .iter()
+        .map(|(c, a)| format_amount(*a, Some(c)))
+        .collect();
+    writeln!(
+        out,
+        "<tr><th>Total</th><th>{}</th><th class=\"number\">{}</th></tr>\n</table>",
+        escape_html(&format.format(total)),
+        escape_html(&totals.join(", "))
+    )
+    .unwrap();
+
+    let top_level: Vec<(&str, Duration)> = roll_up
+        .iter()
+        .filter(|r| r.depth == 0)
+        .map(|r| (r.project.as_str(), r.duration))
+        .collect();
+    writeln!(out, "<h2>Share of time</h2>\n{}", pie_chart(&top_level)).unwrap();
+    writeln!(
+        out,
+        "<h2>Time per project</h2>\n{}",
+        bar_chart(&roll_up, format)
+    )
+    .unwrap();
+    writeln!(out, "<h2>Timeline</h2>\n{}", timeline(days, format)).unwrap();
+    writeln!(out, "</body>\n</html>").unwrap();
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{Duration, NaiveDate, TimeZone, Utc};
+
+    use super::{pie_chart, render_html_report, timeline};
+    use crate::duration::DurationFormat;
+    use crate::report::{group_by_day, summarise_projects};
+    use crate::rounding::Rounding;
+    use crate::tasks::Task;
+
+    fn task(project: &str, start: (u32, u32), end: (u32, u32)) -> Task {
+        Task::new(
+            project.to_owned(),
+            Utc.with_ymd_and_hms(2022, 7, start.0, start.1, 0, 0)
+                .unwrap(),
+            Utc.with_ymd_and_hms(2022, 7, end.0, end.1, 0, 0).unwrap(),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_pie_chart() {
+        let svg = pie_chart(&[("a", Duration::hours(1)), ("b", Duration::hours(1))]);
+        assert!(svg.contains("M 100.00 100.00 L 100.00 0.00 A 100 100 0 0 1 100.00 200.00 Z"));
+        assert!(svg.contains("M 100.00 100.00 L 100.00 200.00 A 100 100 0 0 1 100.00 0.00 Z"));
+
+        let svg = pie_chart(&[("a", Duration::hours(1))]);
+        assert!(svg.contains("<circle cx=\"100\" cy=\"100\" r=\"100\""));
+    }
+
+    #[test]
+    fn test_timeline() {
+        let mut days = BTreeMap::new();
+        days.insert(
+            NaiveDate::from_ymd_opt(2022, 7, 1).unwrap(),
+            vec![task("a", (1, 6), (1, 12)), task("b", (1, 18), (2, 0))],
+        );
+
+        let svg = timeline(&days, DurationFormat::Clock);
+        assert!(svg.contains("<rect x=\"300.00\" y=\"25\" width=\"140.00\""));
+        assert!(svg.contains("<rect x=\"580.00\" y=\"25\" width=\"140.00\""));
+    }
+
+    #[test]
+    fn test_render_html_report() {
+        let from = Utc.with_ymd_and_hms(2022, 7, 1, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2022, 7, 8, 0, 0, 0).unwrap();
+        let tasks = [task("<a>", (1, 9), (1, 12)), task("<a>/b", (2, 22), (3, 1))];
+        let summaries = summarise_projects(&tasks, &[], &Rounding::default());
+        let html = render_html_report(
+            from,
+            to,
+            &summaries,
+            &group_by_day(&tasks, from, to),
+            DurationFormat::Clock,
+        );
+
+        assert!(html.contains("<h1>Time report 2022-07-01 to 2022-07-07</h1>"));
+        assert!(html.contains("&lt;a&gt;/b"));
+        assert!(!html.contains("<a>"));
+        assert!(html.contains("<th>Total</th><th>06:00</th>"));
+        // Task crossing midnight is shown on both days
+        assert_eq!(html.matches("<title>&lt;a&gt;/b from").count(), 2);
+        // No external assets, only the SVG namespace
+        assert!(!html.contains("src="));
+        assert!(!html.contains("href="));
+    }
+}
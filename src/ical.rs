@@ -0,0 +1,524 @@
+//! Export of tasks to, and import of events from, iCalendar (.ics) files.
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::tasks::Task;
+
+/// iCalendar date-time format, always written in UTC.
+const DATETIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+/// Maximum line length in octets, longer lines are folded.
+const MAX_LINE_LENGTH: usize = 75;
+
+/// Escape text property values, e.g. summaries and descriptions.
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+/// Split on commas which aren't escaped, e.g. for lists of categories.
+fn split_escaped_list(text: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            ',' if !escaped => {
+                values.push(unescape_text(&text[start..i]));
+                start = i + 1;
+            }
+            _ => escaped = false,
+        }
+    }
+    values.push(unescape_text(&text[start..]));
+
+    values.into_iter().filter(|v| !v.is_empty()).collect()
+}
+
+/// Write content line, folded onto continuation lines if it's too long.
+fn write_line(out: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            out.push_str("\r\n ");
+            // Continuation lines start with a space, which counts towards the length
+            length = 1;
+        }
+        out.push(c);
+        length += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Unique identifier for the event for `task`, which stays the same if it's
+/// exported again so calendars can update the event.
+fn event_uid(task: &Task) -> String {
+    let project: String = task
+        .project_name()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    format!(
+        "{}-{}@timekeep",
+        task.start_time().format(DATETIME_FORMAT),
+        project
+    )
+}
+
+/// Render `tasks` as a calendar with an event for each task, the project
+/// is used as the event summary and tags as its categories.
+pub fn render_calendar(tasks: &[Task]) -> String {
+    let stamp = Utc::now().format(DATETIME_FORMAT);
+
+    let mut out = String::new();
+    write_line(&mut out, "BEGIN:VCALENDAR");
+    write_line(&mut out, "VERSION:2.0");
+    write_line(&mut out, "PRODID:-//timekeep//timekeep//EN");
+    for task in tasks {
+        write_line(&mut out, "BEGIN:VEVENT");
+        write_line(&mut out, &format!("UID:{}", event_uid(task)));
+        write_line(&mut out, &format!("DTSTAMP:{}", stamp));
+        write_line(
+            &mut out,
+            &format!("DTSTART:{}", task.start_time().format(DATETIME_FORMAT)),
+        );
+        write_line(
+            &mut out,
+            &format!("DTEND:{}", task.end_time().format(DATETIME_FORMAT)),
+        );
+        write_line(
+            &mut out,
+            &format!("SUMMARY:{}", escape_text(task.project_name())),
+        );
+        if let Some(d) = task.description() {
+            write_line(&mut out, &format!("DESCRIPTION:{}", escape_text(d)));
+        }
+        if !task.tags().is_empty() {
+            let tags: Vec<String> = task.tags().iter().map(|t| escape_text(t)).collect();
+            write_line(&mut out, &format!("CATEGORIES:{}", tags.join(",")));
+        }
+        write_line(&mut out, "END:VEVENT");
+    }
+    write_line(&mut out, "END:VCALENDAR");
+
+    out
+}
+
+/// Calendar event read from an .ics file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub categories: Vec<String>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// Content line split into its name, parameters and value.
+struct ContentLine<'a> {
+    name: String,
+    params: Vec<(String, &'a str)>,
+    value: &'a str,
+}
+
+impl ContentLine<'_> {
+    fn parse(line: &str) -> Result<ContentLine<'_>> {
+        // Parameter values can be quoted and contain colons, the value can't be
+        let mut quoted = false;
+        let colon = line
+            .char_indices()
+            .find(|(_, c)| {
+                if *c == '"' {
+                    quoted = !quoted;
+                }
+                *c == ':' && !quoted
+            })
+            .map(|(i, _)| i)
+            .ok_or_else(|| anyhow!("invalid calendar line {:?}", line))?;
+
+        let mut parts = line[..colon].split(';');
+        let name = parts.next().unwrap_or_default().to_ascii_uppercase();
+        let params = parts
+            .filter_map(|p| p.split_once('='))
+            .map(|(k, v)| (k.to_ascii_uppercase(), v.trim_matches('"')))
+            .collect();
+
+        Ok(ContentLine {
+            name,
+            params,
+            value: &line[colon + 1..],
+        })
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|p| p.0 == name).map(|p| p.1)
+    }
+}
+
+/// Parse date-time value, times without a "Z" suffix are treated as UTC
+/// (the same as times given on the command line). Time zones other than UTC
+/// aren't supported, so times with any other TZID are an error.
+///
+/// Returns `None` for dates without times, i.e. all-day events.
+fn parse_datetime(line: &ContentLine) -> Result<Option<DateTime<Utc>>> {
+    if line.param("VALUE") == Some("DATE") || !line.value.contains('T') {
+        return Ok(None);
+    }
+    if let Some(tzid) = line.param("TZID") {
+        if !["UTC", "Etc/UTC"]
+            .iter()
+            .any(|z| z.eq_ignore_ascii_case(tzid))
+        {
+            return Err(anyhow!(
+                "time zone {:?} not supported for {:?}, times must be in UTC",
+                tzid,
+                line.value
+            ));
+        }
+    }
+
+    let value = line.value.trim_end_matches('Z');
+    let datetime = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .map_err(|e| anyhow!("invalid date-time {:?}: {}", line.value, e))?;
+    Ok(Some(Utc.from_utc_datetime(&datetime)))
+}
+
+/// Parse duration value, e.g. "PT1H30M" or "P1D".
+fn parse_duration(text: &str) -> Result<Duration> {
+    let invalid = || anyhow!("invalid duration {:?}", text);
+
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(t) => (true, t),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let text = text.strip_prefix('P').ok_or_else(invalid)?;
+
+    let mut duration = Duration::zero();
+    let mut number = String::new();
+    let mut time = false;
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        if c == 'T' {
+            time = true;
+            continue;
+        }
+
+        let n: i64 = number.parse().map_err(|_| invalid())?;
+        number.clear();
+        let part = match (c, time) {
+            ('W', false) => Duration::try_weeks(n),
+            ('D', false) => Duration::try_days(n),
+            ('H', true) => Duration::try_hours(n),
+            ('M', true) => Duration::try_minutes(n),
+            ('S', true) => Duration::try_seconds(n),
+            _ => None,
+        };
+        duration = part
+            .and_then(|p| duration.checked_add(&p))
+            .ok_or_else(invalid)?;
+    }
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(if negative { -duration } else { duration })
+}
+
+/// Parse events from the calendar `text`.
+///
+/// All-day events are skipped as they don't cover a specific time.
+pub fn parse_calendar(text: &str) -> Result<Vec<Event>> {
+    // Unfold continuation lines, which start with a space or tab
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+
+    let mut events = Vec::new();
+    // Components currently inside, e.g. VCALENDAR, VEVENT then VALARM
+    let mut components: Vec<String> = Vec::new();
+    let mut uid = None;
+    let mut summary = None;
+    let mut description = None;
+    let mut categories = Vec::new();
+    let mut start = None;
+    let mut end = None;
+    let mut duration = None;
+    let mut all_day = false;
+
+    for line in lines.iter().filter(|l| !l.trim().is_empty()) {
+        let line = ContentLine::parse(line)?;
+        match line.name.as_str() {
+            "BEGIN" => {
+                if line.value.eq_ignore_ascii_case("VEVENT") {
+                    uid = None;
+                    summary = None;
+                    description = None;
+                    categories = Vec::new();
+                    start = None;
+                    end = None;
+                    duration = None;
+                    all_day = false;
+                }
+                components.push(line.value.to_ascii_uppercase());
+                continue;
+            }
+            "END" => {
+                let component = components.pop();
+                if component.as_deref() != Some("VEVENT") || all_day {
+                    continue;
+                }
+
+                let start: DateTime<Utc> =
+                    start.ok_or_else(|| anyhow!("event has no start time"))?;
+                let end = match (end, duration) {
+                    (Some(e), _) => e,
+                    (None, Some(d)) => start
+                        .checked_add_signed(d)
+                        .ok_or_else(|| anyhow!("event duration out of range"))?,
+                    (None, None) => start,
+                };
+                if end < start {
+                    return Err(anyhow!(
+                        "event {:?} ends before it starts",
+                        summary.as_deref().unwrap_or_default()
+                    ));
+                }
+                events.push(Event {
+                    uid: uid.take(),
+                    summary: summary.take(),
+                    description: description.take(),
+                    categories: std::mem::take(&mut categories),
+                    start,
+                    end,
+                });
+                continue;
+            }
+            _ => {}
+        }
+
+        // Only properties of the event itself, not e.g. its alarms
+        if components.last().map(|c| c.as_str()) != Some("VEVENT") {
+            continue;
+        }
+        match line.name.as_str() {
+            "UID" => uid = Some(line.value.to_owned()),
+            "SUMMARY" => summary = Some(unescape_text(line.value)),
+            "DESCRIPTION" => description = Some(unescape_text(line.value)),
+            "CATEGORIES" => categories.extend(split_escaped_list(line.value)),
+            "DTSTART" => match parse_datetime(&line)? {
+                Some(dt) => start = Some(dt),
+                None => all_day = true,
+            },
+            "DTEND" => end = parse_datetime(&line)?,
+            "DURATION" => duration = Some(parse_duration(line.value)?),
+            _ => {}
+        }
+    }
+
+    Ok(events)
+}
+
+/// Rules for choosing the project for imported calendar events.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CategoryRules {
+    /// Project for events in each category, categories are matched ignoring case.
+    pub categories: BTreeMap<String, String>,
+    /// Project for events which don't match any category, events are skipped if not set.
+    pub default_project: Option<String>,
+}
+
+impl CategoryRules {
+    /// Project for an event in `categories`, the first category with a rule is used.
+    pub fn project_for(&self, categories: &[String]) -> Option<&str> {
+        categories
+            .iter()
+            .find_map(|c| {
+                self.categories
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(c))
+                    .map(|(_, p)| p.as_str())
+            })
+            .or(self.default_project.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::{parse_calendar, parse_duration, render_calendar, CategoryRules};
+    use crate::tasks::Task;
+
+    #[test]
+    fn test_render_calendar() {
+        let task = Task::new(
+            "acme/web".to_owned(),
+            Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2022, 7, 1, 10, 30, 0).unwrap(),
+            Some(format!("Fix, test; deploy {}", "x".repeat(70))),
+        )
+        .with_tags(vec!["dev".to_owned()]);
+
+        let text = render_calendar(std::slice::from_ref(&task));
+        assert!(text.contains("UID:20220701T090000Z-acme-web@timekeep\r\n"));
+        assert!(text.contains("DTSTART:20220701T090000Z\r\nDTEND:20220701T103000Z\r\n"));
+        assert!(text.contains("SUMMARY:acme/web\r\n"));
+        assert!(text.contains("DESCRIPTION:Fix\\, test\\; deploy xxx"));
+        assert!(text.lines().all(|l| l.len() <= 75));
+
+        let events = parse_calendar(&text).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].uid.as_deref(),
+            Some("20220701T090000Z-acme-web@timekeep")
+        );
+        assert_eq!(events[0].summary.as_deref(), Some("acme/web"));
+        assert_eq!(events[0].description.as_deref(), task.description());
+        assert_eq!(events[0].categories, vec!["dev"]);
+        assert_eq!(events[0].end - events[0].start, Duration::minutes(90));
+    }
+
+    #[test]
+    fn test_parse_calendar() {
+        let text = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+SUMMARY:Stand-up\r
+DTSTART;TZID=UTC:20220704T093000\r
+DURATION:PT15M\r
+CATEGORIES:Meeting,Team\r
+BEGIN:VALARM\r
+DESCRIPTION:Reminder\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Holiday\r
+DTSTART;VALUE=DATE:20220705\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Planning for the next sprint with a very long title which is fold\r
+ ed\r
+DTSTART:20220706T140000Z\r
+DTEND:20220706T160000Z\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let events = parse_calendar(text).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].summary.as_deref(), Some("Stand-up"));
+        assert_eq!(events[0].description, None);
+        assert_eq!(events[0].categories, vec!["Meeting", "Team"]);
+        assert_eq!(
+            events[0].start,
+            Utc.with_ymd_and_hms(2022, 7, 4, 9, 30, 0).unwrap()
+        );
+        assert_eq!(events[0].end - events[0].start, Duration::minutes(15));
+        assert_eq!(
+            events[1].summary.as_deref(),
+            Some("Planning for the next sprint with a very long title which is folded")
+        );
+
+        let mut rules = CategoryRules::default();
+        rules
+            .categories
+            .insert("meeting".to_owned(), "work/meetings".to_owned());
+        assert_eq!(
+            rules.project_for(&events[0].categories),
+            Some("work/meetings")
+        );
+        assert_eq!(rules.project_for(&events[1].categories), None);
+
+        rules.default_project = Some("other".to_owned());
+        assert_eq!(rules.project_for(&events[1].categories), Some("other"));
+    }
+
+    #[test]
+    fn test_parse_calendar_invalid() {
+        let cases = [
+            (
+                "DTSTART:20220704T093000Z\r\nDURATION:-PT15M",
+                "ends before it starts",
+            ),
+            (
+                "DTSTART:20220704T093000Z\r\nDTEND:20220704T090000Z",
+                "ends before it starts",
+            ),
+            (
+                "DTSTART;TZID=\"Europe/London\":20220704T093000",
+                "not supported",
+            ),
+            (
+                "DTSTART:20220704T093000Z\r\nDURATION:P99999999W",
+                "out of range",
+            ),
+        ];
+        for (event, expected) in cases {
+            let text = format!(
+                "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\n{}\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n",
+                event
+            );
+            let error = parse_calendar(&text).unwrap_err();
+            assert!(
+                error.to_string().contains(expected),
+                "testing: {:?} failed with {:?}",
+                event,
+                error.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        let cases = [
+            ("PT15M", Duration::minutes(15)),
+            ("PT1H30M", Duration::minutes(90)),
+            ("P1DT2H", Duration::hours(26)),
+            ("P2W", Duration::weeks(2)),
+            ("-PT10S", Duration::seconds(-10)),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(
+                parse_duration(text).unwrap(),
+                expected,
+                "testing: parse_duration({:?})",
+                text
+            );
+        }
+        assert!(parse_duration("1H").is_err());
+        assert!(parse_duration("PT1").is_err());
+        assert!(parse_duration("P99999999999999W").is_err());
+        assert!(parse_duration("PT9223372036854775807S").is_err());
+    }
+}
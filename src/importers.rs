@@ -0,0 +1,381 @@
+//! Importing tasks from other time trackers' export formats.
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::ical::{self, CategoryRules};
+use crate::tasks::Task;
+
+/// Format of a file to import tasks from.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// iCalendar events, projects are chosen by the category rules
+    Ics,
+    /// Toggl Track detailed report CSV
+    Toggl,
+    /// Clockify detailed report CSV
+    Clockify,
+    /// Output of `timew export`, the first tag is used as the project
+    Timewarrior,
+}
+
+impl ImportFormat {
+    /// Guess format from the extension of `path`, CSV files are ambiguous so
+    /// give `None`.
+    pub fn from_path(path: &Path) -> Option<ImportFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ics" => Some(ImportFormat::Ics),
+            "json" => Some(ImportFormat::Timewarrior),
+            _ => None,
+        }
+    }
+}
+
+/// Task read from an import file, with a key identifying the record it came
+/// from so importing the same file again doesn't duplicate tasks.
+#[derive(Debug, Clone)]
+pub struct ImportedTask {
+    pub key: String,
+    pub task: Task,
+}
+
+/// Tasks read from an import file, and the number of records skipped because
+/// no project could be found for them.
+#[derive(Debug)]
+pub struct ImportedTasks {
+    pub tasks: Vec<ImportedTask>,
+    pub skipped: usize,
+}
+
+impl ImportedTasks {
+    fn new() -> ImportedTasks {
+        ImportedTasks {
+            tasks: Vec::new(),
+            skipped: 0,
+        }
+    }
+
+    /// Add task for `project`, or count it as skipped if there isn't a project.
+    fn push(
+        &mut self,
+        key: String,
+        project: Option<&str>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        description: Option<String>,
+        tags: Vec<String>,
+    ) {
+        match project.filter(|p| !p.is_empty()) {
+            Some(p) => self.tasks.push(ImportedTask {
+                key,
+                task: Task::new(p.to_owned(), start, end, description).with_tags(tags),
+            }),
+            None => self.skipped += 1,
+        }
+    }
+}
+
+/// Read tasks in `format` from `text`.
+///
+/// Records without a project use the rules' default project, if any, and
+/// calendar events use the rules to choose a project from their categories.
+pub fn read_tasks(
+    format: ImportFormat,
+    text: &str,
+    rules: &CategoryRules,
+) -> Result<ImportedTasks> {
+    // Spreadsheet programs often add a byte order mark to CSV files
+    let text = text.trim_start_matches('\u{feff}');
+    match format {
+        ImportFormat::Ics => read_ics(text, rules),
+        ImportFormat::Toggl => read_csv(text, "toggl", rules),
+        ImportFormat::Clockify => read_csv(text, "clockify", rules),
+        ImportFormat::Timewarrior => read_timewarrior(text, rules),
+    }
+}
+
+fn read_ics(text: &str, rules: &CategoryRules) -> Result<ImportedTasks> {
+    let mut imported = ImportedTasks::new();
+
+    for event in ical::parse_calendar(text)? {
+        // Recurring events share a UID so the start is needed to tell them apart
+        let key = format!(
+            "ics:{}:{}",
+            event.uid.as_deref().unwrap_or_default(),
+            event.start.to_rfc3339()
+        );
+        imported.push(
+            key,
+            rules.project_for(&event.categories),
+            event.start,
+            event.end,
+            event.summary.or(event.description),
+            Vec::new(),
+        );
+    }
+
+    Ok(imported)
+}
+
+/// Split comma separated list of tags, removing any spaces around them.
+fn split_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_owned())
+        .collect()
+}
+
+/// Parse date and time from separate CSV columns, in any of the formats the
+/// trackers use depending on the user's settings. Times are treated as UTC.
+fn parse_csv_datetime(date: &str, time: &str) -> Result<DateTime<Utc>> {
+    let date = ["%Y-%m-%d", "%m/%d/%Y", "%d.%m.%Y"]
+        .iter()
+        .find_map(|f| NaiveDate::parse_from_str(date.trim(), f).ok())
+        .ok_or_else(|| anyhow!("invalid date {:?}", date))?;
+    let time = ["%H:%M:%S", "%I:%M:%S %p", "%H:%M", "%I:%M %p"]
+        .iter()
+        .find_map(|f| NaiveTime::parse_from_str(time.trim(), f).ok())
+        .ok_or_else(|| anyhow!("invalid time {:?}", time))?;
+
+    Ok(Utc.from_utc_datetime(&NaiveDateTime::new(date, time)))
+}
+
+/// Row from a Toggl or Clockify CSV export, with the columns used for tasks.
+///
+/// The trackers use the same column names apart from their capitalisation.
+#[derive(Deserialize)]
+struct CsvRow {
+    #[serde(rename = "Project", default)]
+    project: String,
+    #[serde(rename = "Description", default)]
+    description: String,
+    #[serde(rename = "Tags", default)]
+    tags: String,
+    #[serde(rename = "Start date", alias = "Start Date")]
+    start_date: String,
+    #[serde(rename = "Start time", alias = "Start Time")]
+    start_time: String,
+    #[serde(rename = "End date", alias = "End Date")]
+    end_date: String,
+    #[serde(rename = "End time", alias = "End Time")]
+    end_time: String,
+}
+
+/// Read CSV export, `source` is used in the import keys.
+fn read_csv(text: &str, source: &str, rules: &CategoryRules) -> Result<ImportedTasks> {
+    let mut imported = ImportedTasks::new();
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+
+    for (i, row) in reader.deserialize::<CsvRow>().enumerate() {
+        // Line numbers start at 1 and the header is the first line
+        let invalid = |e: anyhow::Error| anyhow!("invalid {} CSV row {}: {}", source, i + 2, e);
+        let row = row.map_err(|e| invalid(e.into()))?;
+        let start = parse_csv_datetime(&row.start_date, &row.start_time).map_err(invalid)?;
+        let end = parse_csv_datetime(&row.end_date, &row.end_time).map_err(invalid)?;
+        if end < start {
+            return Err(invalid(anyhow!("end time before start time")));
+        }
+        let description = Some(row.description.trim().to_owned()).filter(|d| !d.is_empty());
+
+        let key = format!(
+            "{}:{}:{}:{}:{}",
+            source,
+            start.to_rfc3339(),
+            end.to_rfc3339(),
+            row.project,
+            row.description
+        );
+        let project = Some(row.project.trim())
+            .filter(|p| !p.is_empty())
+            .or(rules.default_project.as_deref());
+        imported.push(key, project, start, end, description, split_tags(&row.tags));
+    }
+
+    Ok(imported)
+}
+
+/// Interval from `timew export`.
+#[derive(Deserialize)]
+struct TimewarriorInterval {
+    start: String,
+    end: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    annotation: Option<String>,
+}
+
+fn parse_timewarrior_datetime(text: &str) -> Result<DateTime<Utc>> {
+    let datetime = NaiveDateTime::parse_from_str(text, "%Y%m%dT%H%M%SZ")
+        .map_err(|e| anyhow!("invalid timewarrior date-time {:?}: {}", text, e))?;
+    Ok(Utc.from_utc_datetime(&datetime))
+}
+
+/// Read timewarrior intervals, the first tag is used as the project and the
+/// rest as the task's tags. Intervals which are still open are skipped.
+fn read_timewarrior(text: &str, rules: &CategoryRules) -> Result<ImportedTasks> {
+    let intervals: Vec<TimewarriorInterval> = serde_json::from_str(text)?;
+    let mut imported = ImportedTasks::new();
+
+    for (i, interval) in intervals.into_iter().enumerate() {
+        let invalid = |e: anyhow::Error| anyhow!("invalid timewarrior interval {}: {}", i + 1, e);
+        let end = match &interval.end {
+            Some(e) => parse_timewarrior_datetime(e).map_err(invalid)?,
+            None => {
+                imported.skipped += 1;
+                continue;
+            }
+        };
+        let start = parse_timewarrior_datetime(&interval.start).map_err(invalid)?;
+        if end < start {
+            return Err(invalid(anyhow!("end time before start time")));
+        }
+
+        let mut tags = interval.tags.into_iter();
+        let project = tags.next().or_else(|| rules.default_project.clone());
+        // Intervals can't overlap so the start identifies them, unlike their ids
+        imported.push(
+            format!("timewarrior:{}", start.to_rfc3339()),
+            project.as_deref(),
+            start,
+            end,
+            interval.annotation,
+            tags.collect(),
+        );
+    }
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::{read_tasks, ImportFormat};
+    use crate::ical::CategoryRules;
+
+    #[test]
+    fn test_from_path() {
+        let cases = [
+            ("meetings.ICS", Some(ImportFormat::Ics)),
+            ("timew.json", Some(ImportFormat::Timewarrior)),
+            ("toggl.csv", None),
+            ("export", None),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(
+                ImportFormat::from_path(Path::new(path)),
+                expected,
+                "testing: format for {}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_toggl() {
+        let text = "\u{feff}User,Email,Client,Project,Task,Description,Billable,Start date,Start time,End date,End time,Duration,Tags,Amount ()
+Ann,ann@example.com,Acme,Website,,\"Fix header, again\",Yes,2022-07-01,09:00:00,2022-07-01,10:30:00,01:30:00,\"dev, urgent\",
+Ann,ann@example.com,,,,Lunch,No,2022-07-01,12:00:00,2022-07-01,12:30:00,00:30:00,,
+";
+        let imported = read_tasks(ImportFormat::Toggl, text, &CategoryRules::default()).unwrap();
+        assert_eq!(imported.skipped, 1);
+        assert_eq!(imported.tasks.len(), 1);
+
+        let task = &imported.tasks[0].task;
+        assert_eq!(task.project_name(), "Website");
+        assert_eq!(task.description(), Some("Fix header, again"));
+        assert_eq!(task.tags(), ["dev", "urgent"]);
+        assert_eq!(
+            task.start_time(),
+            &Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap()
+        );
+        assert_eq!(task.duration(), Duration::minutes(90));
+
+        let rules = CategoryRules {
+            default_project: Some("other".to_owned()),
+            ..Default::default()
+        };
+        let imported = read_tasks(ImportFormat::Toggl, text, &rules).unwrap();
+        assert_eq!(imported.skipped, 0);
+        assert_eq!(imported.tasks[1].task.project_name(), "other");
+        assert_ne!(imported.tasks[0].key, imported.tasks[1].key);
+    }
+
+    #[test]
+    fn test_read_clockify() {
+        let text = "Project,Client,Description,Task,User,Group,Email,Tags,Billable,Start Date,Start Time,End Date,End Time,Duration (h),Duration (decimal)
+Website,Acme,Deploy,,Ann,,ann@example.com,ops,Yes,07/01/2022,11:30:00 PM,07/02/2022,12:15:00 AM,00:45:00,0.75
+";
+        let imported = read_tasks(ImportFormat::Clockify, text, &CategoryRules::default()).unwrap();
+        let task = &imported.tasks[0].task;
+        assert_eq!(task.project_name(), "Website");
+        assert_eq!(task.tags(), ["ops"]);
+        assert_eq!(
+            task.start_time(),
+            &Utc.with_ymd_and_hms(2022, 7, 1, 23, 30, 0).unwrap()
+        );
+        assert_eq!(task.duration(), Duration::minutes(45));
+
+        let missing = "Project,Description\nWebsite,Deploy\n";
+        assert!(read_tasks(ImportFormat::Clockify, missing, &CategoryRules::default()).is_err());
+
+        let backwards = format!(
+            "{}Website,Acme,Deploy,,Ann,,ann@example.com,,Yes,07/01/2022,10:00:00 AM,07/01/2022,09:00:00 AM,-01:00:00,-1.00\n",
+            text
+        );
+        let error = read_tasks(
+            ImportFormat::Clockify,
+            &backwards,
+            &CategoryRules::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid clockify CSV row 3: end time before start time"
+        );
+    }
+
+    #[test]
+    fn test_read_timewarrior() {
+        let text = r#"[
+            {"id": 3, "start": "20220701T090000Z", "end": "20220701T100000Z", "tags": ["acme", "dev"], "annotation": "Review"},
+            {"id": 2, "start": "20220701T110000Z", "end": "20220701T113000Z"},
+            {"id": 1, "start": "20220701T120000Z", "tags": ["acme"]}
+        ]"#;
+        let imported =
+            read_tasks(ImportFormat::Timewarrior, text, &CategoryRules::default()).unwrap();
+        assert_eq!(imported.tasks.len(), 1);
+        assert_eq!(imported.skipped, 2);
+
+        let task = &imported.tasks[0].task;
+        assert_eq!(task.project_name(), "acme");
+        assert_eq!(task.tags(), ["dev"]);
+        assert_eq!(task.description(), Some("Review"));
+        assert_eq!(
+            imported.tasks[0].key,
+            "timewarrior:2022-07-01T09:00:00+00:00"
+        );
+
+        let backwards = r#"[
+            {"id": 2, "start": "20220701T090000Z", "end": "20220701T100000Z", "tags": ["acme"]},
+            {"id": 1, "start": "20220701T120000Z", "end": "20220701T110000Z", "tags": ["acme"]}
+        ]"#;
+        let error = read_tasks(
+            ImportFormat::Timewarrior,
+            backwards,
+            &CategoryRules::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid timewarrior interval 2: end time before start time"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Rendering of per-client invoice statements.
+use std::fmt::Write;
+
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+
+use crate::report::{self, format_amount, hours, ProjectSummary};
+
+/// Output format for invoices.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvoiceFormat {
+    Markdown,
+    Html,
+    Csv,
+}
+
+/// Escape text for including in HTML.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Quote CSV field if it contains separators, quotes or new lines.
+pub fn escape_csv(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+/// Single row of the invoice, one for each project and rate.
+struct InvoiceRow {
+    project: String,
+    hours: String,
+    rate: String,
+    amount: String,
+}
+
+fn invoice_rows(summaries: &[ProjectSummary]) -> Vec<InvoiceRow> {
+    let mut rows = Vec::new();
+    for summary in summaries {
+        let currency = summary.currency.as_deref();
+        for line in &summary.lines {
+            rows.push(InvoiceRow {
+                project: summary.project.clone(),
+                hours: format!("{:.2}", hours(line.duration)),
+                rate: line
+                    .rate
+                    .map(|r| format_amount(r, currency))
+                    .unwrap_or_default(),
+                amount: line
+                    .amount()
+                    .map(|a| format_amount(a, currency))
+                    .unwrap_or_default(),
+            });
+        }
+    }
+
+    rows
+}
+
+/// Render statement of time billed to `client` between `from` and `to`.
+///
+/// `summaries` should only contain the client's projects.
+pub fn render_invoice(
+    client: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    summaries: &[ProjectSummary],
+    format: InvoiceFormat,
+) -> String {
+    // End time is exclusive so show the last day included
+    let period = format!(
+        "{} to {}",
+        from.naive_local().format("%Y-%m-%d"),
+        (to - Duration::seconds(1)).naive_local().format("%Y-%m-%d")
+    );
+    let rows = invoice_rows(summaries);
+    let total_hours = format!(
+        "{:.2}",
+        summaries.iter().map(|s| hours(s.duration)).sum::<f64>()
+    );
+    let totals: Vec<String> = report::currency_totals(summaries)
+        .iter()
+        .map(|(c, a)| format_amount(*a, Some(c)))
+        .collect();
+    let total_amount = totals.join(", ");
+
+    // Writing to a String never fails
+    let mut out = String::new();
+    match format {
+        InvoiceFormat::Markdown => {
+            writeln!(out, "# Statement for {}\n", client).unwrap();
+            writeln!(out, "Period: {}\n", period).unwrap();
+            writeln!(out, "| Project | Hours | Rate | Amount |").unwrap();
+            writeln!(out, "| --- | ---: | ---: | ---: |").unwrap();
+            for r in &rows {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} |",
+                    r.project.replace('|', "\\|"),
+                    r.hours,
+                    r.rate,
+                    r.amount
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "| **Total** | **{}** | | **{}** |",
+                total_hours, total_amount
+            )
+            .unwrap();
+        }
+
+        InvoiceFormat::Html => {
+            let title = format!("Statement for {}", escape_html(client));
+            writeln!(
+                out,
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+            )
+            .unwrap();
+            writeln!(out, "<title>{}</title>\n</head>\n<body>", title).unwrap();
+            writeln!(out, "<h1>{}</h1>\n<p>Period: {}</p>", title, period).unwrap();
+            writeln!(out, "<table>").unwrap();
+            writeln!(
+                out,
+                "<tr><th>Project</th><th>Hours</th><th>Rate</th><th>Amount</th></tr>"
+            )
+            .unwrap();
+            for r in &rows {
+                writeln!(
+                    out,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    escape_html(&r.project),
+                    r.hours,
+                    escape_html(&r.rate),
+                    escape_html(&r.amount)
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "<tr><th>Total</th><th>{}</th><th></th><th>{}</th></tr>",
+                total_hours,
+                escape_html(&total_amount)
+            )
+            .unwrap();
+            writeln!(out, "</table>\n</body>\n</html>").unwrap();
+        }
+
+        InvoiceFormat::Csv => {
+            writeln!(out, "client,project,hours,rate,amount").unwrap();
+            for r in &rows {
+                writeln!(
+                    out,
+                    "{},{},{},{},{}",
+                    escape_csv(client),
+                    escape_csv(&r.project),
+                    r.hours,
+                    escape_csv(&r.rate),
+                    escape_csv(&r.amount)
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "{},Total,{},,{}",
+                escape_csv(client),
+                total_hours,
+                escape_csv(&total_amount)
+            )
+            .unwrap();
+        }
+    };
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape_csv;
+
+    #[test]
+    fn test_escape_csv() {
+        assert_eq!(escape_csv("plain"), "plain");
+        assert_eq!(escape_csv("a,b"), "\"a,b\"");
+        assert_eq!(escape_csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -1,11 +1,37 @@
 //! # Timekeep
 //! Small command-line tool for tracking time spent on projects and tasks.
+pub mod backup;
+pub mod chart;
 pub mod cli;
+pub mod completions;
+pub mod config;
 pub mod database;
+pub mod duration;
+pub mod fuzzy;
+pub mod hooks;
+pub mod html_report;
+pub mod ical;
+pub mod importers;
+pub mod invoice;
+pub mod period;
+pub mod projects;
+pub mod query;
+pub mod reminders;
+pub mod report;
+pub mod rounding;
+pub mod server;
+pub mod stats;
+pub mod status;
+pub mod table;
+pub mod targets;
 pub mod tasks;
+pub mod timekeep;
+pub mod tui;
 
+pub use projects::Project;
 pub use tasks::CurrentTask;
 pub use tasks::Task;
+pub use timekeep::Timekeep;
 
 /// Crate version number.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -15,6 +41,8 @@ const DATA_DIRECTORY: &str = "timekeep";
 const CURRENT_ACTIVITY_FILE: &str = "current.json";
 /// Database file name.
 const DATABASE_FILE: &str = "timekeep.db";
+/// Config file name.
+const CONFIG_FILE: &str = "config.json";
 
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -25,6 +53,7 @@ use anyhow::{anyhow, Result};
 pub struct DataFiles {
     current_file: PathBuf,
     database_file: PathBuf,
+    config_file: PathBuf,
     data_folder: PathBuf,
 }
 
@@ -59,29 +88,31 @@ impl DataFiles {
         Ok(DataFiles {
             current_file: data_folder.join(current_activity_file),
             database_file: data_folder.join(database_file),
+            config_file: data_folder.join(CONFIG_FILE),
             data_folder,
         })
     }
 
-    /// JSON file storing the current running task.
+    /// Legacy JSON file which stored the current running task.
+    ///
+    /// The current task is now stored in the database, this file is only
+    /// read to import tasks started by older versions.
     pub fn current_file(&self) -> &Path {
         &self.current_file
     }
 
-    /// SQLite database storing all completed tasks and projects.
+    /// SQLite database storing the current task, all completed tasks and projects.
     pub fn database_file(&self) -> &Path {
         &self.database_file
     }
 
+    /// JSON file storing user configuration.
+    pub fn config_file(&self) -> &Path {
+        &self.config_file
+    }
+
     /// Folder containing all persistent storage for timekeep.
     pub fn data_folder(&self) -> &Path {
         &self.data_folder
     }
 }
-
-mod projects {
-    struct Project {
-        name: String,
-        description: Option<String>,
-    }
-}
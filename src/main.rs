@@ -1,32 +1,131 @@
+use std::process::ExitCode;
+
 use anyhow::Result;
 use clap::Parser;
 use directories::BaseDirs;
 
-use timekeep::cli::{Cli, Commands};
-use timekeep::{cli, DataFiles};
+use timekeep::cli::{Cli, Commands, ProjectCommands};
+use timekeep::config::Config;
+use timekeep::{cli, tui, DataFiles, Timekeep};
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(cli::EXIT_ERROR)
+        }
+    }
+}
 
-fn main() -> Result<()> {
+fn run() -> Result<ExitCode> {
     let base_dirs = BaseDirs::new().unwrap();
     let files = DataFiles::new(base_dirs.data_dir())?;
 
     let cli = Cli::parse();
+    let mut config = Config::load(files.config_file())?;
+    if let Some(format) = cli.duration_format {
+        config.duration_format = format;
+    }
+    let timekeep = Timekeep::with_config(files, config)?;
 
+    let mut exit_code = ExitCode::SUCCESS;
     match &cli.command {
         Commands::Start {
             project_name,
             start_time,
             description,
             overwrite,
-        } => cli::start(&files, project_name, start_time, description, overwrite)?,
-        Commands::End { end_time, discard } => cli::end(&files, end_time, discard)?,
+            tags,
+        } => cli::start(
+            &timekeep,
+            project_name,
+            start_time,
+            description,
+            overwrite,
+            tags,
+        )?,
+        Commands::End { end_time, discard } => cli::end(&timekeep, end_time, discard)?,
         Commands::Add {
             project_name,
             start_time,
             end_time,
             description,
-        } => cli::add(&files, project_name, start_time, end_time, description)?,
-        Commands::View { filter, from, to } => cli::view(&files, *filter, from, to)?,
+            tags,
+        } => cli::add(
+            &timekeep,
+            project_name,
+            start_time,
+            end_time,
+            description,
+            tags,
+        )?,
+        Commands::Edit { id, changes } => cli::edit(&timekeep, *id, changes)?,
+        Commands::Delete { id } => cli::delete(&timekeep, *id)?,
+        Commands::Status { format, json } => cli::status(&timekeep, format, *json)?,
+        Commands::View {
+            range,
+            filter,
+            flat,
+            chart,
+            rounding,
+        } => exit_code = cli::view(&timekeep, range, filter, *flat, *chart, rounding)?,
+        Commands::Report {
+            range,
+            filter,
+            chart,
+            html,
+            rounding,
+        } => cli::report(&timekeep, range, filter, *chart, html, rounding)?,
+        Commands::Invoice {
+            client,
+            range,
+            format,
+            output,
+            rounding,
+        } => cli::invoice(&timekeep, client, range, *format, output, rounding)?,
+        Commands::Balance => cli::balance(&timekeep)?,
+        Commands::Stats { range, project } => cli::stats(&timekeep, range, project)?,
+        Commands::Export {
+            range,
+            project,
+            output,
+            all,
+        } => match all {
+            Some(path) => cli::export_backup(&timekeep, path)?,
+            None => cli::export(&timekeep, range, project, output)?,
+        },
+        Commands::Import {
+            file,
+            format,
+            dry_run,
+            restore,
+            force,
+            maps,
+            project,
+        } => match (restore, file) {
+            (Some(path), _) => cli::restore_backup(&timekeep, path, *force)?,
+            (None, Some(file)) => cli::import(&timekeep, file, *format, *dry_run, maps, project)?,
+            (None, None) => unreachable!("clap requires a file if not restoring"),
+        },
+        Commands::Project { command } => match command {
+            ProjectCommands::Set {
+                name,
+                description,
+                client,
+                rate,
+                currency,
+            } => cli::project_set(&timekeep, name, description, client, rate, currency)?,
+            ProjectCommands::Rate { name, tag, rate } => {
+                cli::project_rate(&timekeep, name, tag, rate)?
+            }
+            ProjectCommands::List { names } => cli::project_list(&timekeep, *names)?,
+        },
+        Commands::Tui => tui::run(&timekeep)?,
+        Commands::Daemon { once } => cli::daemon(&timekeep, *once)?,
+        Commands::Serve { port, address } => cli::serve(&timekeep, address, *port)?,
+        Commands::Completions { shell } => cli::completions(*shell)?,
     };
 
-    Ok(())
+    Ok(exit_code)
 }
@@ -0,0 +1,372 @@
+//! Calendar periods used for filtering and summarising tasks.
+use anyhow::{anyhow, Result};
+use chrono::{
+    DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
+};
+use clap::ValueEnum;
+
+/// First day (Monday) of the ISO week containing `day`.
+pub fn week_start(day: NaiveDate) -> NaiveDate {
+    day - Duration::days(day.weekday().num_days_from_monday() as i64)
+}
+
+/// Start and end of the range covering all tasks.
+pub fn all_time() -> (DateTime<Utc>, DateTime<Utc>) {
+    (
+        Utc.with_ymd_and_hms(0, 1, 1, 0, 0, 0).unwrap(),
+        Utc.with_ymd_and_hms(9999, 1, 1, 0, 0, 0).unwrap(),
+    )
+}
+
+/// Length of a calendar period.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeriodKind {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl PeriodKind {
+    /// Number of months in the period, `None` for periods measured in days.
+    fn months(&self) -> Option<i32> {
+        match self {
+            PeriodKind::Day | PeriodKind::Week => None,
+            PeriodKind::Month => Some(1),
+            PeriodKind::Quarter => Some(3),
+            PeriodKind::Year => Some(12),
+        }
+    }
+}
+
+/// Single calendar day, ISO week, month, quarter or year.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    kind: PeriodKind,
+    start: NaiveDate,
+}
+
+/// First day of the month `months` after the start of year 0.
+fn month_from_index(months: i32) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(months.div_euclid(12), months.rem_euclid(12) as u32 + 1, 1)
+}
+
+fn month_index(day: NaiveDate) -> i32 {
+    day.year() * 12 + day.month0() as i32
+}
+
+impl Period {
+    /// Period of `kind` which contains `day`.
+    pub fn containing(kind: PeriodKind, day: NaiveDate) -> Period {
+        let start = match kind.months() {
+            None if kind == PeriodKind::Day => day,
+            None => week_start(day),
+            Some(m) => {
+                let index = month_index(day);
+                month_from_index(index - index.rem_euclid(m))
+                    .expect("first day of the month containing a valid date is valid")
+            }
+        };
+
+        Period { kind, start }
+    }
+
+    /// Period of `kind` containing today.
+    pub fn current(kind: PeriodKind) -> Period {
+        Period::containing(kind, Utc::now().date_naive())
+    }
+
+    /// Parse period of `kind` from `text`, relative to `today`.
+    ///
+    /// Accepts "today", "yesterday", "tomorrow", "last", "next" or any date
+    /// (YYYY-MM-DD) in the period. Periods can also be given as YYYY-Www for
+    /// weeks, YYYY-MM for months, YYYY-Qn for quarters and YYYY for years.
+    pub fn parse(kind: PeriodKind, text: &str, today: NaiveDate) -> Result<Period> {
+        let current = Period::containing(kind, today);
+        let relative = match text.to_lowercase().as_str() {
+            "today" | "this" => Some(current),
+            "yesterday" | "last" | "previous" => current.offset(-1),
+            "tomorrow" | "next" => current.offset(1),
+            _ => None,
+        };
+        if let Some(p) = relative {
+            return Ok(p);
+        }
+
+        if let Ok(day) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+            return Ok(Period::containing(kind, day));
+        }
+
+        let invalid = || anyhow!("invalid {:?} period: {:?}", kind, text);
+        let (year, rest) = match text.split_once('-') {
+            Some((y, r)) => (y, Some(r)),
+            None => (text, None),
+        };
+        let year: i32 = year.parse().map_err(|_| invalid())?;
+
+        let start = match (kind, rest) {
+            (PeriodKind::Year, None) => NaiveDate::from_ymd_opt(year, 1, 1),
+            (PeriodKind::Month, Some(m)) => {
+                NaiveDate::from_ymd_opt(year, m.parse().map_err(|_| invalid())?, 1)
+            }
+            (PeriodKind::Quarter, Some(q)) => match q.strip_prefix(['Q', 'q']) {
+                Some(q) => match q.parse::<u32>() {
+                    Ok(q @ 1..=4) => NaiveDate::from_ymd_opt(year, (q - 1) * 3 + 1, 1),
+                    _ => None,
+                },
+                None => None,
+            },
+            (PeriodKind::Week, Some(w)) => match w.strip_prefix(['W', 'w']) {
+                Some(w) => NaiveDate::from_isoywd_opt(
+                    year,
+                    w.parse().map_err(|_| invalid())?,
+                    Weekday::Mon,
+                ),
+                None => None,
+            },
+            _ => None,
+        };
+
+        start
+            .map(|s| Period::containing(kind, s))
+            .ok_or_else(invalid)
+    }
+
+    pub fn kind(&self) -> PeriodKind {
+        self.kind
+    }
+
+    /// First day of the period.
+    pub fn start(&self) -> NaiveDate {
+        self.start
+    }
+
+    /// First day after the period.
+    pub fn end(&self) -> NaiveDate {
+        self.offset(1).map(|p| p.start).unwrap_or(NaiveDate::MAX)
+    }
+
+    /// Period `n` periods after this one, or before if `n` is negative.
+    ///
+    /// Returns `None` if the period is outside the supported range of dates.
+    pub fn offset(&self, n: i32) -> Option<Period> {
+        let start = match self.kind.months() {
+            None => {
+                let days = if self.kind == PeriodKind::Day { 1 } else { 7 };
+                self.start
+                    .checked_add_signed(Duration::days(days * n as i64))?
+            }
+            Some(m) => month_from_index(month_index(self.start).checked_add(m.checked_mul(n)?)?)?,
+        };
+
+        Some(Period {
+            kind: self.kind,
+            start,
+        })
+    }
+
+    /// Start and end (exclusive) times of the period.
+    pub fn range(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+        (
+            Utc.from_utc_datetime(&self.start().and_time(NaiveTime::MIN)),
+            Utc.from_utc_datetime(&self.end().and_time(NaiveTime::MIN)),
+        )
+    }
+}
+
+/// Parse datetime string which doesn't include timezone, use local timezone.
+///
+/// If date isn't given then today is used, if time isn't given then 00:00:00
+/// is used.
+pub fn parse_local_datetime(text: &str) -> Result<DateTime<Utc>> {
+    // Attempt to parse datetime and fallback on parsing only date or time
+    let datetime = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+        .or(NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M"));
+
+    let datetime = match datetime {
+        Ok(dt) => dt,
+        Err(_) => {
+            // Parse string as date only
+            let date = NaiveDate::parse_from_str(text, "%Y-%m-%d");
+
+            if let Ok(date) = date {
+                date.and_time(NaiveTime::MIN)
+            } else {
+                // Parse string as time only and use today's date
+                let time = NaiveTime::parse_from_str(text, "%H:%M:%S")
+                    .or(NaiveTime::parse_from_str(text, "%H:%M"))?;
+
+                NaiveDateTime::new(Utc::now().date_naive(), time)
+            }
+        }
+    };
+
+    Ok(Utc.from_local_datetime(&datetime).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use super::{week_start, Period, PeriodKind};
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_week_start() {
+        let monday = date(2022, 6, 27);
+        for offset in 0..7 {
+            let day = monday + chrono::Duration::days(offset);
+            assert_eq!(week_start(day), monday, "testing: week_start({})", day);
+        }
+    }
+
+    #[test]
+    fn test_containing() {
+        let day = date(2022, 8, 17);
+        let tests = [
+            (PeriodKind::Day, date(2022, 8, 17), date(2022, 8, 18)),
+            (PeriodKind::Week, date(2022, 8, 15), date(2022, 8, 22)),
+            (PeriodKind::Month, date(2022, 8, 1), date(2022, 9, 1)),
+            (PeriodKind::Quarter, date(2022, 7, 1), date(2022, 10, 1)),
+            (PeriodKind::Year, date(2022, 1, 1), date(2023, 1, 1)),
+        ];
+
+        for (kind, start, end) in tests {
+            let period = Period::containing(kind, day);
+            assert_eq!(
+                (period.start(), period.end()),
+                (start, end),
+                "testing: {:?} containing {}",
+                kind,
+                day
+            );
+        }
+    }
+
+    #[test]
+    fn test_offset() {
+        let month = Period::containing(PeriodKind::Month, date(2022, 1, 31));
+        assert_eq!(month.offset(-1).unwrap().start(), date(2021, 12, 1));
+        assert_eq!(month.offset(13).unwrap().start(), date(2023, 2, 1));
+
+        let quarter = Period::containing(PeriodKind::Quarter, date(2022, 2, 1));
+        assert_eq!(quarter.offset(-2).unwrap().start(), date(2021, 7, 1));
+
+        let week = Period::containing(PeriodKind::Week, date(2022, 1, 1));
+        assert_eq!(week.offset(1).unwrap().start(), date(2022, 1, 3));
+
+        assert_eq!(month.offset(i32::MAX), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let today = date(2022, 8, 17);
+        let tests = [
+            (PeriodKind::Day, "yesterday", date(2022, 8, 16)),
+            (PeriodKind::Day, "2022-01-05", date(2022, 1, 5)),
+            (PeriodKind::Week, "last", date(2022, 8, 8)),
+            (PeriodKind::Week, "2022-W01", date(2022, 1, 3)),
+            (PeriodKind::Month, "2022-03", date(2022, 3, 1)),
+            (PeriodKind::Month, "next", date(2022, 9, 1)),
+            (PeriodKind::Quarter, "2022-Q4", date(2022, 10, 1)),
+            (PeriodKind::Year, "2021", date(2021, 1, 1)),
+        ];
+
+        for (kind, text, start) in tests {
+            assert_eq!(
+                Period::parse(kind, text, today).unwrap().start(),
+                start,
+                "testing: parse {:?} {:?}",
+                kind,
+                text
+            );
+        }
+
+        assert!(Period::parse(PeriodKind::Month, "2022-13", today).is_err());
+        assert!(Period::parse(PeriodKind::Quarter, "2022-Q5", today).is_err());
+        assert!(Period::parse(PeriodKind::Year, "2022-01", today).is_err());
+    }
+
+    /// Test parsing text with date and time.
+    #[test]
+    fn datetime_parse_valid() {
+        let mut tests = Vec::new();
+        tests.push((
+            "2022-02-01 13:14:15",
+            NaiveDate::from_ymd_opt(2022, 2, 1)
+                .unwrap()
+                .and_hms_opt(13, 14, 15)
+                .unwrap(),
+        ));
+        tests.push((
+            "2022-02-01 01:02",
+            NaiveDate::from_ymd_opt(2022, 2, 1)
+                .unwrap()
+                .and_hms_opt(1, 2, 0)
+                .unwrap(),
+        ));
+        tests.push((
+            "2022-2-1 1:2:3",
+            NaiveDate::from_ymd_opt(2022, 2, 1)
+                .unwrap()
+                .and_hms_opt(1, 2, 3)
+                .unwrap(),
+        ));
+        tests.push((
+            "2022-2-1 1:2",
+            NaiveDate::from_ymd_opt(2022, 2, 1)
+                .unwrap()
+                .and_hms_opt(1, 2, 0)
+                .unwrap(),
+        ));
+        tests.push((
+            "2022-2-1",
+            NaiveDate::from_ymd_opt(2022, 2, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+        ));
+
+        for (s, t) in tests {
+            let t = Utc.from_local_datetime(&t).unwrap();
+
+            assert_eq!(
+                super::parse_local_datetime(s).unwrap(),
+                t,
+                "testing: parse_local_datetime({}) == {:?}",
+                s,
+                t
+            );
+        }
+    }
+
+    /// Test parsing text with time only.
+    #[test]
+    fn datetime_parse_time() {
+        let mut tests = Vec::new();
+        tests.push((
+            "11:12:1",
+            Utc::now().date_naive().and_hms_opt(11, 12, 1).unwrap(),
+        ));
+        tests.push((
+            "11:12",
+            Utc::now().date_naive().and_hms_opt(11, 12, 0).unwrap(),
+        ));
+
+        for (s, t) in tests {
+            let t = Utc.from_local_datetime(&t).unwrap();
+
+            assert_eq!(
+                super::parse_local_datetime(s).unwrap(),
+                t,
+                "testing: parse_local_datetime({}) == {:?}",
+                s,
+                t
+            );
+        }
+    }
+}
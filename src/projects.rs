@@ -0,0 +1,232 @@
+//! Project type storing details used for reporting and billing.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Project which tasks are recorded against, identified by its name.
+///
+/// Tasks can use project names which haven't been stored as a `Project`, in
+/// which case the project has no rate or client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Project {
+    name: String,
+    description: Option<String>,
+    client: Option<String>,
+    rate: Option<f64>,
+    currency: Option<String>,
+    #[serde(default)]
+    tag_rates: BTreeMap<String, f64>,
+}
+
+impl Project {
+    pub fn new(name: String) -> Project {
+        Project {
+            name,
+            description: None,
+            client: None,
+            rate: None,
+            currency: None,
+            tag_rates: BTreeMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn set_description(&mut self, description: Option<String>) {
+        self.description = description;
+    }
+
+    /// Client the project is billed to.
+    pub fn client(&self) -> Option<&str> {
+        self.client.as_deref()
+    }
+
+    pub fn set_client(&mut self, client: Option<String>) {
+        self.client = client;
+    }
+
+    /// Default hourly rate for tasks in the project.
+    pub fn rate(&self) -> Option<f64> {
+        self.rate
+    }
+
+    pub fn set_rate(&mut self, rate: Option<f64>) {
+        self.rate = rate;
+    }
+
+    pub fn currency(&self) -> Option<&str> {
+        self.currency.as_deref()
+    }
+
+    pub fn set_currency(&mut self, currency: Option<String>) {
+        self.currency = currency;
+    }
+
+    /// Hourly rates overriding the project rate for tasks with certain tags.
+    pub fn tag_rates(&self) -> &BTreeMap<String, f64> {
+        &self.tag_rates
+    }
+
+    /// Set the hourly rate for tasks with `tag`, or remove the override if `None`.
+    pub fn set_tag_rate(&mut self, tag: &str, rate: Option<f64>) {
+        match rate {
+            Some(r) => self.tag_rates.insert(tag.to_owned(), r),
+            None => self.tag_rates.remove(tag),
+        };
+    }
+
+    /// Hourly rate for a task with `tags`.
+    ///
+    /// The first tag with a rate override is used, otherwise the project rate.
+    pub fn rate_for(&self, tags: &[String]) -> Option<f64> {
+        tags.iter()
+            .find_map(|t| self.tag_rates.get(t).copied())
+            .or(self.rate)
+    }
+}
+
+/// Separator between levels of hierarchical project names, e.g. `client/project`.
+pub const SEPARATOR: char = '/';
+
+/// Names of all levels above project `name`, starting from the top level.
+///
+/// e.g. `client/project/sub` has ancestors `client` and `client/project`.
+pub fn ancestors(name: &str) -> Vec<&str> {
+    name.match_indices(SEPARATOR)
+        .map(|(i, _)| &name[..i])
+        .collect()
+}
+
+/// Find the stored project for `name`, falling back on the closest parent
+/// project so sub-projects use the client and rates of their parent.
+pub fn find_project<'a>(projects: &'a [Project], name: &str) -> Option<&'a Project> {
+    std::iter::once(name)
+        .chain(ancestors(name).into_iter().rev())
+        .find_map(|n| projects.iter().find(|p| p.name() == n))
+}
+
+/// Pattern for matching hierarchical project names.
+///
+/// Each level of the pattern is matched against a level of the name, `*`
+/// matches any single level and `**` matches any number of levels (including
+/// none), so `client/**` matches `client` and every project under it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectPattern(String);
+
+impl ProjectPattern {
+    pub fn new(pattern: &str) -> ProjectPattern {
+        ProjectPattern(pattern.to_owned())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Check if project `name` matches the pattern.
+    pub fn matches(&self, name: &str) -> bool {
+        let pattern: Vec<&str> = self.0.split(SEPARATOR).collect();
+        let name: Vec<&str> = name.split(SEPARATOR).collect();
+        match_levels(&pattern, &name)
+    }
+}
+
+impl std::str::FromStr for ProjectPattern {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(ProjectPattern::new(s))
+    }
+}
+
+fn match_levels(pattern: &[&str], name: &[&str]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(&"**"), _) => {
+            // Match no levels, or consume one level and try again
+            match_levels(&pattern[1..], name)
+                || (!name.is_empty() && match_levels(pattern, &name[1..]))
+        }
+        (Some(&"*"), Some(_)) => match_levels(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) => p == n && match_levels(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ancestors, find_project, Project, ProjectPattern};
+
+    #[test]
+    fn test_rate_for() {
+        let mut project = Project::new("test".to_owned());
+        assert_eq!(project.rate_for(&[]), None);
+
+        project.set_rate(Some(50.0));
+        project.set_tag_rate("meeting", Some(30.0));
+        project.set_tag_rate("support", Some(80.0));
+
+        let tags = |t: &[&str]| t.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(project.rate_for(&tags(&[])), Some(50.0));
+        assert_eq!(project.rate_for(&tags(&["other"])), Some(50.0));
+        assert_eq!(project.rate_for(&tags(&["support", "meeting"])), Some(80.0));
+
+        project.set_tag_rate("support", None);
+        assert_eq!(project.rate_for(&tags(&["support", "meeting"])), Some(30.0));
+    }
+
+    #[test]
+    fn test_ancestors() {
+        assert_eq!(ancestors("client"), Vec::<&str>::new());
+        assert_eq!(
+            ancestors("client/project/sub"),
+            vec!["client", "client/project"]
+        );
+    }
+
+    #[test]
+    fn test_find_project() {
+        let projects = [
+            Project::new("client".to_owned()),
+            Project::new("client/project".to_owned()),
+        ];
+
+        let found = |n| find_project(&projects, n).map(|p| p.name());
+        assert_eq!(found("client/project"), Some("client/project"));
+        assert_eq!(found("client/project/sub"), Some("client/project"));
+        assert_eq!(found("client/other"), Some("client"));
+        assert_eq!(found("other"), None);
+    }
+
+    #[test]
+    fn test_project_pattern() {
+        let tests = [
+            ("client", "client", true),
+            ("client", "client/project", false),
+            ("client/**", "client", true),
+            ("client/**", "client/project/sub", true),
+            ("client/**", "clientele", false),
+            ("client/*", "client", false),
+            ("client/*", "client/project", true),
+            ("client/*", "client/project/sub", false),
+            ("**/sub", "client/project/sub", true),
+            ("*/project/**", "client/project/sub", true),
+        ];
+
+        for (pattern, name, a) in tests {
+            assert_eq!(
+                ProjectPattern::new(pattern).matches(name),
+                a,
+                "testing: {} matches {} == {}",
+                pattern,
+                name,
+                a
+            );
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Composable queries selecting stored tasks, compiled to parameterised SQL.
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use rusqlite::types::Value;
+
+use crate::period;
+use crate::projects::ProjectPattern;
+
+/// Which of a task's times must be within a query's time range.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RangeMatch {
+    /// Tasks started within the range.
+    #[default]
+    Start,
+    /// Tasks ended within the range.
+    End,
+    /// Tasks with any time within the range.
+    Overlap,
+}
+
+/// Order of the tasks returned by a query.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    StartTime,
+    EndTime,
+    Duration,
+    Project,
+}
+
+/// Selection of stored tasks, built by chaining the methods for each condition.
+///
+/// By default every task is selected, ordered by start time. Tasks must match
+/// every condition given, but only one of the project patterns.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TaskQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    range_match: RangeMatch,
+    projects: Vec<ProjectPattern>,
+    tags: Vec<String>,
+    search: Option<String>,
+    sort: SortKey,
+    descending: bool,
+    limit: Option<u32>,
+    offset: u32,
+}
+
+/// Escape the wildcards of `LIKE` patterns in `text`, using `\` as the escape character.
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+impl TaskQuery {
+    pub fn new() -> TaskQuery {
+        TaskQuery::default()
+    }
+
+    /// Only tasks from `from`, inclusive.
+    pub fn from(mut self, from: DateTime<Utc>) -> TaskQuery {
+        self.from = Some(from);
+        self
+    }
+
+    /// Only tasks before `to`, exclusive.
+    pub fn to(mut self, to: DateTime<Utc>) -> TaskQuery {
+        self.to = Some(to);
+        self
+    }
+
+    /// Only tasks from `from` until `to`.
+    pub fn range(self, from: DateTime<Utc>, to: DateTime<Utc>) -> TaskQuery {
+        self.from(from).to(to)
+    }
+
+    /// Compare the range with the task's start time, end time, or whole task.
+    pub fn range_match(mut self, range_match: RangeMatch) -> TaskQuery {
+        self.range_match = range_match;
+        self
+    }
+
+    /// Only tasks for projects matching `pattern`, or any other pattern given.
+    pub fn project(mut self, pattern: ProjectPattern) -> TaskQuery {
+        self.projects.push(pattern);
+        self
+    }
+
+    /// Only tasks for projects matching any of `patterns`, e.g. an optional pattern.
+    pub fn projects(mut self, patterns: impl IntoIterator<Item = ProjectPattern>) -> TaskQuery {
+        self.projects.extend(patterns);
+        self
+    }
+
+    /// Only tasks with `tag`, along with any other tags given.
+    pub fn tag(mut self, tag: &str) -> TaskQuery {
+        self.tags.push(tag.to_owned());
+        self
+    }
+
+    /// Only tasks with all of `tags`.
+    pub fn tags(mut self, tags: &[String]) -> TaskQuery {
+        self.tags.extend_from_slice(tags);
+        self
+    }
+
+    /// Only tasks whose description contains `text`, ignoring ASCII case.
+    pub fn search(mut self, text: &str) -> TaskQuery {
+        self.search = Some(text.to_owned());
+        self
+    }
+
+    /// Order tasks by `key`, ties are ordered by start time then id.
+    pub fn sort_by(mut self, key: SortKey) -> TaskQuery {
+        self.sort = key;
+        self
+    }
+
+    /// Reverse the order of the tasks.
+    pub fn descending(mut self, descending: bool) -> TaskQuery {
+        self.descending = descending;
+        self
+    }
+
+    /// Return at most `limit` tasks.
+    pub fn limit(mut self, limit: u32) -> TaskQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skip the first `offset` tasks.
+    pub fn offset(mut self, offset: u32) -> TaskQuery {
+        self.offset = offset;
+        self
+    }
+
+    /// Start and end of the query's range, covering all time if not given.
+    pub fn bounds(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+        let (min, max) = period::all_time();
+        (self.from.unwrap_or(min), self.to.unwrap_or(max))
+    }
+
+    /// SQL statement selecting `columns` of the matching rows of the `tasks`
+    /// table, with the values of its numbered parameters.
+    pub fn to_sql(&self, columns: &str) -> (String, Vec<Value>) {
+        let mut params = Vec::new();
+        let mut param = |value: Value| {
+            params.push(value);
+            format!("?{}", params.len())
+        };
+
+        let mut conditions = Vec::new();
+        // Column and operator compared with the start and end of the range
+        let ((from_column, from_op), (to_column, to_op)) = match self.range_match {
+            RangeMatch::Start => (("start_time", ">="), ("start_time", "<")),
+            RangeMatch::End => (("end_time", ">="), ("end_time", "<")),
+            RangeMatch::Overlap => (("end_time", ">"), ("start_time", "<")),
+        };
+        if let Some(from) = self.from {
+            let from = param(Value::Text(from.to_rfc3339()));
+            conditions.push(format!("{} {} {}", from_column, from_op, from));
+        }
+        if let Some(to) = self.to {
+            let to = param(Value::Text(to.to_rfc3339()));
+            conditions.push(format!("{} {} {}", to_column, to_op, to));
+        }
+        if !self.projects.is_empty() {
+            let patterns: Vec<String> = self
+                .projects
+                .iter()
+                .map(|p| {
+                    let pattern = param(Value::Text(p.as_str().to_owned()));
+                    format!("project_matches({}, project_name)", pattern)
+                })
+                .collect();
+            conditions.push(format!("({})", patterns.join(" OR ")));
+        }
+        // Tags are stored comma separated, and can't contain commas
+        for tag in &self.tags {
+            let tag = param(Value::Text(format!(",{},", tag)));
+            conditions.push(format!("instr(',' || tags || ',', {}) > 0", tag));
+        }
+        if let Some(text) = &self.search {
+            let text = param(Value::Text(format!("%{}%", escape_like(text))));
+            conditions.push(format!("description LIKE {} ESCAPE '\\'", text));
+        }
+
+        let mut sql = format!("SELECT {} FROM tasks", columns);
+        if !conditions.is_empty() {
+            sql.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+        }
+
+        let key = match self.sort {
+            SortKey::StartTime => "start_time",
+            SortKey::EndTime => "end_time",
+            SortKey::Duration => "julianday(end_time) - julianday(start_time)",
+            SortKey::Project => "project_name",
+        };
+        let mut keys = vec![key];
+        if self.sort != SortKey::StartTime {
+            keys.push("start_time");
+        }
+        keys.push("id");
+        let order = if self.descending { " DESC" } else { "" };
+        let keys: Vec<String> = keys.iter().map(|k| format!("{}{}", k, order)).collect();
+        sql.push_str(&format!(" ORDER BY {}", keys.join(", ")));
+
+        // SQLite needs a limit to give an offset, negative is no limit
+        if self.limit.is_some() || self.offset > 0 {
+            let limit = param(Value::Integer(self.limit.map(i64::from).unwrap_or(-1)));
+            let offset = param(Value::Integer(self.offset.into()));
+            sql.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset));
+        }
+
+        (sql + ";", params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use rusqlite::types::Value;
+
+    use super::{RangeMatch, SortKey, TaskQuery};
+    use crate::projects::ProjectPattern;
+
+    #[test]
+    fn test_to_sql() {
+        let from = Utc.with_ymd_and_hms(2022, 7, 1, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2022, 7, 2, 0, 0, 0).unwrap();
+        let text = |s: &str| Value::Text(s.to_owned());
+
+        let cases = [
+            (
+                TaskQuery::new(),
+                "SELECT id FROM tasks ORDER BY start_time, id;",
+                vec![],
+            ),
+            (
+                TaskQuery::new().range(from, to),
+                "SELECT id FROM tasks WHERE start_time >= ?1 AND start_time < ?2 \
+                    ORDER BY start_time, id;",
+                vec![text("2022-07-01T00:00:00+00:00"), text("2022-07-02T00:00:00+00:00")],
+            ),
+            (
+                TaskQuery::new().range(from, to).range_match(RangeMatch::End),
+                "SELECT id FROM tasks WHERE end_time >= ?1 AND end_time < ?2 \
+                    ORDER BY start_time, id;",
+                vec![text("2022-07-01T00:00:00+00:00"), text("2022-07-02T00:00:00+00:00")],
+            ),
+            (
+                TaskQuery::new()
+                    .to(to)
+                    .range_match(RangeMatch::Overlap)
+                    .project(ProjectPattern::new("acme/**"))
+                    .projects(Some(ProjectPattern::new("internal"))),
+                "SELECT id FROM tasks WHERE start_time < ?1 AND \
+                    (project_matches(?2, project_name) OR project_matches(?3, project_name)) \
+                    ORDER BY start_time, id;",
+                vec![text("2022-07-02T00:00:00+00:00"), text("acme/**"), text("internal")],
+            ),
+            (
+                TaskQuery::new()
+                    .tag("bug")
+                    .search("50%_done")
+                    .sort_by(SortKey::Duration)
+                    .descending(true)
+                    .offset(10),
+                "SELECT id FROM tasks WHERE instr(',' || tags || ',', ?1) > 0 AND \
+                    description LIKE ?2 ESCAPE '\\' \
+                    ORDER BY julianday(end_time) - julianday(start_time) DESC, start_time DESC, id DESC \
+                    LIMIT ?3 OFFSET ?4;",
+                vec![
+                    text(",bug,"),
+                    text("%50\\%\\_done%"),
+                    Value::Integer(-1),
+                    Value::Integer(10),
+                ],
+            ),
+        ];
+        for (query, expected_sql, expected_params) in cases {
+            let (sql, params) = query.to_sql("id");
+            assert_eq!(sql, expected_sql, "testing: {:?}", query);
+            assert_eq!(params, expected_params, "testing: {:?}", query);
+        }
+    }
+}
@@ -0,0 +1,489 @@
+//! Reminders about long running tasks, or no task during working hours,
+//! checked periodically by `timekeep daemon`.
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::duration::DurationFormat;
+use crate::tasks::CurrentTask;
+use crate::timekeep::Timekeep;
+
+/// Hours of the day when a task is expected to be running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkingHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+/// How reminders are delivered.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NotifierConfig {
+    /// Desktop notification, using `notify-send` or `osascript` on macOS.
+    #[default]
+    Desktop,
+    /// Shell command, given the reminder in `TIMEKEEP_REMINDER` and `TIMEKEEP_MESSAGE`.
+    Command { command: String },
+    /// Line appended to a file, relative paths are in the data folder.
+    Log { file: PathBuf },
+}
+
+/// When to remind, no reminders are given unless a threshold or working
+/// hours are set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Reminders {
+    /// Remind once the current task has been running this many minutes.
+    pub long_task_minutes: Option<i64>,
+    /// Remind when no task is running between these times on working days.
+    pub working_hours: Option<WorkingHours>,
+    /// Minutes without a task during working hours before reminding.
+    pub idle_minutes: i64,
+    /// Minutes to wait before repeating a reminder.
+    pub repeat_minutes: i64,
+    pub notifier: NotifierConfig,
+}
+
+impl Default for Reminders {
+    fn default() -> Self {
+        Reminders {
+            long_task_minutes: None,
+            working_hours: None,
+            idle_minutes: 15,
+            repeat_minutes: 30,
+            notifier: NotifierConfig::default(),
+        }
+    }
+}
+
+/// Something the user should be reminded about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reminder {
+    /// Current task has been running for a long time.
+    LongTask { project: String, elapsed: Duration },
+    /// No task has been running for a while during working hours.
+    NoTask { idle: Duration },
+}
+
+impl Reminder {
+    /// Short name of the kind of reminder, for hooks.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Reminder::LongTask { .. } => "long_task",
+            Reminder::NoTask { .. } => "no_task",
+        }
+    }
+
+    pub fn message(&self, format: DurationFormat) -> String {
+        match self {
+            Reminder::LongTask { project, elapsed } => {
+                format!(
+                    "{} has been running for {}",
+                    project,
+                    format.format(*elapsed)
+                )
+            }
+            Reminder::NoTask { idle } => {
+                format!("No task has been running for {}", format.format(*idle))
+            }
+        }
+    }
+}
+
+/// Source of the current time, replaced in tests.
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Delivers reminders to the user.
+pub trait Notifier {
+    fn notify(&mut self, message: &str, reminder: &Reminder, now: DateTime<Utc>) -> Result<()>;
+}
+
+pub struct DesktopNotifier;
+
+impl Notifier for DesktopNotifier {
+    fn notify(&mut self, message: &str, _: &Reminder, _: DateTime<Utc>) -> Result<()> {
+        let status = if cfg!(target_os = "macos") {
+            let script = format!(
+                "display notification \"{}\" with title \"timekeep\"",
+                message.replace('\\', "\\\\").replace('"', "\\\"")
+            );
+            Command::new("osascript").args(["-e", &script]).status()?
+        } else {
+            Command::new("notify-send")
+                .args(["timekeep", message])
+                .status()?
+        };
+        if !status.success() {
+            return Err(anyhow!("desktop notification failed: {}", status));
+        }
+
+        Ok(())
+    }
+}
+
+pub struct CommandNotifier {
+    pub command: String,
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&mut self, message: &str, reminder: &Reminder, _: DateTime<Utc>) -> Result<()> {
+        let status = Command::new("sh")
+            .args(["-c", &self.command])
+            .env("TIMEKEEP_REMINDER", reminder.kind())
+            .env("TIMEKEEP_MESSAGE", message)
+            .status()?;
+        if !status.success() {
+            return Err(anyhow!("reminder command failed: {}", status));
+        }
+
+        Ok(())
+    }
+}
+
+pub struct LogNotifier {
+    pub file: PathBuf,
+}
+
+impl Notifier for LogNotifier {
+    fn notify(&mut self, message: &str, _: &Reminder, now: DateTime<Utc>) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file)?;
+        writeln!(file, "{} {}", now.to_rfc3339(), message)?;
+
+        Ok(())
+    }
+}
+
+/// Notifier described by `config`, with log files relative to `data_folder`.
+pub fn notifier(config: &NotifierConfig, data_folder: &Path) -> Box<dyn Notifier> {
+    match config {
+        NotifierConfig::Desktop => Box::new(DesktopNotifier),
+        NotifierConfig::Command { command } => Box::new(CommandNotifier {
+            command: command.clone(),
+        }),
+        NotifierConfig::Log { file } => Box::new(LogNotifier {
+            file: data_folder.join(file),
+        }),
+    }
+}
+
+/// What a reminder was about, so it's only repeated after `repeat_minutes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Subject {
+    /// Task started at this time.
+    Task(DateTime<Utc>),
+    /// No task since this time.
+    Idle(DateTime<Utc>),
+}
+
+/// Decides when reminders are due.
+pub struct Watcher {
+    reminders: Reminders,
+    working_days: Vec<Weekday>,
+    last: Option<(Subject, DateTime<Utc>)>,
+}
+
+impl Watcher {
+    pub fn new(reminders: Reminders, working_days: Vec<Weekday>) -> Watcher {
+        Watcher {
+            reminders,
+            working_days,
+            last: None,
+        }
+    }
+
+    /// True if any reminders are configured.
+    pub fn is_enabled(&self) -> bool {
+        self.reminders.long_task_minutes.is_some() || self.reminders.working_hours.is_some()
+    }
+
+    /// Reminder due at `now` for the `current` task, or no task since the
+    /// `last_end` of a task, if one is due and wasn't given recently.
+    pub fn check(
+        &mut self,
+        now: DateTime<Utc>,
+        current: Option<&CurrentTask>,
+        last_end: Option<DateTime<Utc>>,
+    ) -> Option<Reminder> {
+        let (subject, reminder) = match current {
+            Some(task) => {
+                let threshold = Duration::minutes(self.reminders.long_task_minutes?);
+                let elapsed = now - *task.start_time();
+                if elapsed < threshold {
+                    return None;
+                }
+                let reminder = Reminder::LongTask {
+                    project: task.project_name().to_owned(),
+                    elapsed,
+                };
+                (Subject::Task(*task.start_time()), reminder)
+            }
+            None => {
+                let idle_since = self.idle_since(now, last_end)?;
+                let idle = now - idle_since;
+                if idle < Duration::minutes(self.reminders.idle_minutes) {
+                    return None;
+                }
+                (Subject::Idle(idle_since), Reminder::NoTask { idle })
+            }
+        };
+
+        if let Some((last_subject, last_time)) = self.last {
+            let repeat = Duration::minutes(self.reminders.repeat_minutes);
+            if last_subject == subject && now - last_time < repeat {
+                return None;
+            }
+        }
+        self.last = Some((subject, now));
+
+        Some(reminder)
+    }
+
+    /// Start of the time without a task, `None` outside working hours.
+    fn idle_since(
+        &self,
+        now: DateTime<Utc>,
+        last_end: Option<DateTime<Utc>>,
+    ) -> Option<DateTime<Utc>> {
+        let hours = self.reminders.working_hours?;
+        let time = now.time();
+        if !self.working_days.contains(&now.weekday()) || time < hours.start || time >= hours.end {
+            return None;
+        }
+
+        let day_start = Utc.from_utc_datetime(&now.date_naive().and_time(hours.start));
+        Some(last_end.map_or(day_start, |e| e.max(day_start)))
+    }
+}
+
+/// Checks for reminders using the tasks stored by `timekeep`.
+pub struct Daemon<'a> {
+    timekeep: &'a Timekeep,
+    watcher: Watcher,
+    clock: Box<dyn Clock>,
+    notifier: Box<dyn Notifier>,
+}
+
+impl<'a> Daemon<'a> {
+    pub fn new(
+        timekeep: &'a Timekeep,
+        watcher: Watcher,
+        clock: Box<dyn Clock>,
+        notifier: Box<dyn Notifier>,
+    ) -> Daemon<'a> {
+        Daemon {
+            timekeep,
+            watcher,
+            clock,
+            notifier,
+        }
+    }
+
+    /// Send a reminder if one is due, returns the message sent.
+    pub fn tick(&mut self) -> Result<Option<String>> {
+        let now = self.clock.now();
+        let current = self.timekeep.current()?;
+        let last_end = match current {
+            Some(_) => None,
+            None => self.timekeep.last_task()?.map(|t| *t.end_time()),
+        };
+
+        match self.watcher.check(now, current.as_ref(), last_end) {
+            Some(reminder) => {
+                let message = reminder.message(self.timekeep.config().duration_format);
+                self.notifier.notify(&message, &reminder, now)?;
+                Ok(Some(message))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Check for reminders every `interval` until stopped, errors are printed
+    /// and don't stop the daemon.
+    pub fn run(&mut self, interval: StdDuration) -> ! {
+        loop {
+            if let Err(e) = self.tick() {
+                eprintln!("Error: {}", e);
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::env;
+    use std::fs;
+    use std::rc::Rc;
+
+    use anyhow::Result;
+    use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc, Weekday};
+
+    use super::{
+        Clock, Daemon, Notifier, NotifierConfig, Reminder, Reminders, Watcher, WorkingHours,
+    };
+    use crate::config::Config;
+    use crate::duration::DurationFormat;
+    use crate::tasks::CurrentTask;
+    use crate::{DataFiles, Task, Timekeep};
+
+    struct FakeClock(Rc<Cell<DateTime<Utc>>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> DateTime<Utc> {
+            self.0.get()
+        }
+    }
+
+    struct FakeNotifier(Rc<RefCell<Vec<String>>>);
+
+    impl Notifier for FakeNotifier {
+        fn notify(&mut self, message: &str, _: &Reminder, _: DateTime<Utc>) -> Result<()> {
+            self.0.borrow_mut().push(message.to_owned());
+            Ok(())
+        }
+    }
+
+    fn reminders() -> Reminders {
+        Reminders {
+            long_task_minutes: Some(120),
+            working_hours: Some(WorkingHours {
+                start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            }),
+            ..Reminders::default()
+        }
+    }
+
+    #[test]
+    fn test_watcher() {
+        // Friday
+        let day = Utc.with_ymd_and_hms(2022, 7, 1, 0, 0, 0).unwrap();
+        let at = |h: i64, m: i64| day + Duration::hours(h) + Duration::minutes(m);
+        let task = CurrentTask::new("acme".to_owned(), at(9, 0), None);
+
+        let mut watcher = Watcher::new(reminders(), vec![Weekday::Fri]);
+        let cases = [
+            // Long running task, repeated after 30 minutes
+            (at(10, 59), Some(&task), None, None),
+            (at(11, 0), Some(&task), None, Some("acme 2 hr 0 min")),
+            (at(11, 29), Some(&task), None, None),
+            (at(11, 30), Some(&task), None, Some("acme 2 hr 30 min")),
+            // Idle since the start of working hours or the last task
+            (at(9, 14), None, None, None),
+            (at(9, 15), None, None, Some("idle 15 min 0 s")),
+            (at(9, 20), None, None, None),
+            (at(12, 20), None, Some(at(12, 0)), Some("idle 20 min 0 s")),
+            (at(12, 21), None, Some(at(12, 0)), None),
+            (at(12, 50), None, Some(at(12, 0)), Some("idle 50 min 0 s")),
+            // Outside working hours
+            (at(17, 0), None, Some(at(12, 0)), None),
+            (at(34, 0), None, Some(at(12, 0)), None),
+        ];
+        for (now, current, last_end, expected) in cases {
+            let reminder = watcher.check(now, current, last_end);
+            let summary = reminder.map(|r| match r {
+                Reminder::LongTask { project, elapsed } => {
+                    format!("{} {}", project, DurationFormat::Human.format(elapsed))
+                }
+                Reminder::NoTask { idle } => format!("idle {}", DurationFormat::Human.format(idle)),
+            });
+            assert_eq!(
+                summary.as_deref(),
+                expected,
+                "testing: check at {}",
+                now.format("%a %R")
+            );
+        }
+
+        assert!(!Watcher::new(Reminders::default(), vec![]).is_enabled());
+    }
+
+    #[test]
+    fn test_daemon() {
+        let folder = env::temp_dir().join(format!("timekeep-test-daemon-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let files = DataFiles::new(&folder).unwrap();
+        let timekeep = Timekeep::with_config(files, Config::default()).unwrap();
+
+        let now = Rc::new(Cell::new(
+            Utc.with_ymd_and_hms(2022, 7, 1, 8, 0, 0).unwrap(),
+        ));
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let mut daemon = Daemon::new(
+            &timekeep,
+            Watcher::new(reminders(), vec![Weekday::Fri]),
+            Box::new(FakeClock(now.clone())),
+            Box::new(FakeNotifier(sent.clone())),
+        );
+
+        let task = Task::new(
+            "acme".to_owned(),
+            now.get(),
+            now.get() + Duration::hours(2),
+            None,
+        );
+        timekeep.add(task).unwrap();
+        now.set(now.get() + Duration::hours(2) + Duration::minutes(15));
+        assert_eq!(
+            daemon.tick().unwrap().as_deref(),
+            Some("No task has been running for 15 min 0 s")
+        );
+
+        timekeep
+            .start("acme", Some(now.get()), None, &[], false)
+            .unwrap();
+        now.set(now.get() + Duration::hours(3));
+        assert_eq!(
+            daemon.tick().unwrap().as_deref(),
+            Some("acme has been running for 3 hr 0 min")
+        );
+        assert_eq!(daemon.tick().unwrap(), None);
+        assert_eq!(sent.borrow().len(), 2);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_config() {
+        let json = r#"{
+            "long_task_minutes": 90,
+            "working_hours": { "start": "09:00:00", "end": "17:30:00" },
+            "notifier": { "kind": "command", "command": "echo \"$TIMEKEEP_MESSAGE\"" }
+        }"#;
+        let reminders: Reminders = serde_json::from_str(json).unwrap();
+        assert_eq!(reminders.long_task_minutes, Some(90));
+        assert_eq!(reminders.idle_minutes, 15);
+        assert_eq!(
+            reminders.working_hours.unwrap().end,
+            NaiveTime::from_hms_opt(17, 30, 0).unwrap()
+        );
+        assert_eq!(
+            reminders.notifier,
+            NotifierConfig::Command {
+                command: "echo \"$TIMEKEEP_MESSAGE\"".to_owned()
+            }
+        );
+    }
+}
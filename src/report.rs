@@ -0,0 +1,406 @@
+//! Aggregation of tasks into per-project totals for reports and invoices.
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use crate::projects::{self, Project};
+use crate::rounding::Rounding;
+use crate::tasks::Task;
+
+/// Convert duration to a decimal number of hours.
+pub fn hours(duration: Duration) -> f64 {
+    duration.num_milliseconds() as f64 / 3_600_000.0
+}
+
+/// Time spent on a project at a single hourly rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLine {
+    pub rate: Option<f64>,
+    pub duration: Duration,
+}
+
+impl RateLine {
+    /// Amount billed for the line, `None` if there's no rate.
+    pub fn amount(&self) -> Option<f64> {
+        self.rate.map(|r| r * hours(self.duration))
+    }
+}
+
+/// Total time and amount for a single project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectSummary {
+    pub project: String,
+    pub client: Option<String>,
+    pub currency: Option<String>,
+    /// Total duration, rounded according to the rounding scope.
+    pub duration: Duration,
+    /// Time split by the hourly rate it's billed at.
+    pub lines: Vec<RateLine>,
+}
+
+impl ProjectSummary {
+    /// Total amount billed, `None` if none of the project's time has a rate.
+    pub fn amount(&self) -> Option<f64> {
+        self.lines
+            .iter()
+            .filter_map(|l| l.amount())
+            .reduce(|a, b| a + b)
+    }
+}
+
+/// Group `tasks` by the day (UTC) they were worked on, splitting any tasks
+/// which cross midnight into separate parts for each day.
+///
+/// Parts outside the `from` to `to` range are removed and parts crossing its
+/// boundaries are shortened to fit. Each day's parts are sorted by start time.
+pub fn group_by_day(
+    tasks: &[Task],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> BTreeMap<NaiveDate, Vec<Task>> {
+    let mut days: BTreeMap<NaiveDate, Vec<Task>> = BTreeMap::new();
+
+    for part in tasks.iter().flat_map(|t| t.split_days()) {
+        let start = (*part.start_time()).max(from);
+        let end = (*part.end_time()).min(to);
+        if start > end || (start == end && part.duration() > Duration::zero()) {
+            continue;
+        }
+
+        let part = Task::new(
+            part.project_name().to_owned(),
+            start,
+            end,
+            part.description().map(|d| d.to_owned()),
+        )
+        .with_tags(part.tags().to_vec())
+        .with_id(part.id());
+        days.entry(start.date_naive()).or_default().push(part);
+    }
+    for parts in days.values_mut() {
+        parts.sort_by_key(|t| *t.start_time());
+    }
+
+    days
+}
+
+/// Summarise `tasks` by project, sorted by project name.
+///
+/// Rates and clients are taken from the matching entry in `projects`, or its
+/// closest parent project, if there is one. The total for each rate is
+/// rounded with `rounding`, and each project's total is the sum of its rates.
+pub fn summarise_projects(
+    tasks: &[Task],
+    projects: &[Project],
+    rounding: &Rounding,
+) -> Vec<ProjectSummary> {
+    let mut grouped: BTreeMap<&str, Vec<&Task>> = BTreeMap::new();
+    for task in tasks {
+        grouped.entry(task.project_name()).or_default().push(task);
+    }
+
+    grouped
+        .into_iter()
+        .map(|(name, tasks)| {
+            let project = projects::find_project(projects, name);
+
+            let mut rates: Vec<(Option<f64>, Vec<Duration>)> = Vec::new();
+            for task in &tasks {
+                let rate = project.and_then(|p| p.rate_for(task.tags()));
+                match rates.iter_mut().find(|(r, _)| *r == rate) {
+                    Some((_, durations)) => durations.push(task.duration()),
+                    None => rates.push((rate, vec![task.duration()])),
+                }
+            }
+
+            let lines: Vec<RateLine> = rates
+                .into_iter()
+                .map(|(rate, durations)| RateLine {
+                    rate,
+                    duration: rounding.total(durations),
+                })
+                .collect();
+
+            ProjectSummary {
+                project: name.to_owned(),
+                client: project.and_then(|p| p.client()).map(|c| c.to_owned()),
+                currency: project.and_then(|p| p.currency()).map(|c| c.to_owned()),
+                duration: lines.iter().map(|l| l.duration).sum(),
+                lines,
+            }
+        })
+        .collect()
+}
+
+/// Totals for a level of the project hierarchy, including all projects below it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollUp {
+    pub project: String,
+    /// Number of levels above the project, 0 for top level projects.
+    pub depth: usize,
+    pub duration: Duration,
+    /// Amount billed in each currency, see `currency_totals`.
+    pub amounts: BTreeMap<String, f64>,
+}
+
+/// Roll up project `summaries` into totals for every level of the project
+/// hierarchy, sorted so each project is followed by the projects below it.
+pub fn roll_up(summaries: &[ProjectSummary]) -> Vec<RollUp> {
+    let mut levels: BTreeMap<Vec<&str>, RollUp> = BTreeMap::new();
+
+    for summary in summaries {
+        let name = summary.project.as_str();
+        let names = projects::ancestors(name).into_iter().chain([name]);
+
+        for (depth, level) in names.enumerate() {
+            let total = levels
+                .entry(level.split(projects::SEPARATOR).collect())
+                .or_insert_with(|| RollUp {
+                    project: level.to_owned(),
+                    depth,
+                    duration: Duration::zero(),
+                    amounts: BTreeMap::new(),
+                });
+
+            total.duration += summary.duration;
+            if let Some(amount) = summary.amount() {
+                *total
+                    .amounts
+                    .entry(summary.currency.clone().unwrap_or_default())
+                    .or_insert(0.0) += amount;
+            }
+        }
+    }
+
+    levels.into_values().collect()
+}
+
+/// Tasks between `from` and `to`, summarised by project.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub tasks: Vec<Task>,
+    pub summaries: Vec<ProjectSummary>,
+}
+
+impl Report {
+    /// Summarise `tasks` with the rates and clients of `projects`, see `summarise_projects`.
+    pub fn new(
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        tasks: Vec<Task>,
+        projects: &[Project],
+        rounding: &Rounding,
+    ) -> Report {
+        let summaries = summarise_projects(&tasks, projects, rounding);
+        Report {
+            from,
+            to,
+            tasks,
+            summaries,
+        }
+    }
+
+    /// Total time spent on all projects.
+    pub fn total(&self) -> Duration {
+        self.summaries
+            .iter()
+            .fold(Duration::zero(), |acc, s| acc + s.duration)
+    }
+
+    /// Totals for each level of the project hierarchy.
+    pub fn roll_up(&self) -> Vec<RollUp> {
+        roll_up(&self.summaries)
+    }
+
+    /// Total amount billed in each currency.
+    pub fn currency_totals(&self) -> BTreeMap<String, f64> {
+        currency_totals(&self.summaries)
+    }
+
+    /// Tasks worked on each day of the report.
+    pub fn days(&self) -> BTreeMap<NaiveDate, Vec<Task>> {
+        group_by_day(&self.tasks, self.from, self.to)
+    }
+}
+
+/// Total amount for each currency in `summaries`, projects without a
+/// currency are totalled under an empty string.
+pub fn currency_totals(summaries: &[ProjectSummary]) -> BTreeMap<String, f64> {
+    let mut totals = BTreeMap::new();
+    for summary in summaries {
+        if let Some(amount) = summary.amount() {
+            *totals
+                .entry(summary.currency.clone().unwrap_or_default())
+                .or_insert(0.0) += amount;
+        }
+    }
+
+    totals
+}
+
+/// Format monetary `amount` to 2 decimal places, followed by the currency.
+pub fn format_amount(amount: f64, currency: Option<&str>) -> String {
+    match currency {
+        Some(c) if !c.is_empty() => format!("{:.2} {}", amount, c),
+        _ => format!("{:.2}", amount),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, Duration, TimeZone, Utc};
+
+    use super::{group_by_day, roll_up, summarise_projects};
+    use crate::projects::Project;
+    use crate::rounding::{Rounding, RoundingMode, RoundingScope};
+    use crate::tasks::Task;
+
+    fn task(project: &str, minutes: i64, tags: &[&str]) -> Task {
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap();
+        Task::new(
+            project.to_owned(),
+            start,
+            start + Duration::minutes(minutes),
+            None,
+        )
+        .with_tags(tags.iter().map(|t| t.to_string()).collect())
+    }
+
+    #[test]
+    fn test_summarise_projects() {
+        let mut project = Project::new("a".to_owned());
+        project.set_rate(Some(60.0));
+        project.set_tag_rate("meeting", Some(30.0));
+
+        let tasks = [
+            task("b", 10, &[]),
+            task("a", 50, &[]),
+            task("a", 20, &["meeting"]),
+            task("a", 1, &[]),
+        ];
+        let rounding = Rounding {
+            mode: RoundingMode::Up,
+            minutes: 15,
+            scope: RoundingScope::Task,
+        };
+
+        let summaries = summarise_projects(&tasks, &[project.clone()], &rounding);
+        assert_eq!(summaries.len(), 2);
+
+        let a = &summaries[0];
+        assert_eq!(a.project, "a");
+        assert_eq!(a.duration, Duration::minutes(105));
+        assert_eq!(a.lines.len(), 2);
+        assert_eq!(a.amount(), Some(75.0 + 15.0));
+
+        let b = &summaries[1];
+        assert_eq!(b.duration, Duration::minutes(15));
+        assert_eq!(b.amount(), None);
+
+        // Totals are rounded per rate, so the project is the sum of its lines
+        let rounding = Rounding {
+            scope: RoundingScope::Total,
+            ..rounding
+        };
+        let summaries = summarise_projects(&tasks, &[project], &rounding);
+        let a = &summaries[0];
+        let lines: Vec<i64> = a.lines.iter().map(|l| l.duration.num_minutes()).collect();
+        assert_eq!(lines, vec![60, 30]);
+        assert_eq!(a.duration, Duration::minutes(90));
+        assert_eq!(a.amount(), Some(60.0 + 15.0));
+    }
+
+    #[test]
+    fn test_roll_up() {
+        let mut client = Project::new("client".to_owned());
+        client.set_rate(Some(60.0));
+
+        let tasks = [
+            task("client/web/api", 30, &[]),
+            task("client-other", 10, &[]),
+            task("client/web", 15, &[]),
+            task("client/app", 60, &[]),
+        ];
+        let summaries = summarise_projects(&tasks, &[client], &Rounding::default());
+        let rolled = roll_up(&summaries);
+        let totals: Vec<(&str, usize, i64, Option<f64>)> = rolled
+            .iter()
+            .map(|r| {
+                (
+                    r.project.as_str(),
+                    r.depth,
+                    r.duration.num_minutes(),
+                    r.amounts.get("").copied(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            totals,
+            vec![
+                ("client", 0, 105, Some(105.0)),
+                ("client/app", 1, 60, Some(60.0)),
+                ("client/web", 1, 45, Some(45.0)),
+                ("client/web/api", 2, 30, Some(30.0)),
+                ("client-other", 0, 10, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_by_day() {
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 23, 0, 0).unwrap();
+        let tasks = [
+            Task::new("a".to_owned(), start, start + Duration::hours(2), None),
+            Task::new(
+                "b".to_owned(),
+                start + Duration::hours(3),
+                start + Duration::hours(4),
+                None,
+            ),
+        ];
+
+        let from = Utc.with_ymd_and_hms(2022, 7, 1, 0, 0, 0).unwrap();
+        let days = group_by_day(&tasks, from, from + Duration::days(7));
+        let totals: Vec<(u32, Vec<i64>)> = days
+            .iter()
+            .map(|(d, t)| {
+                (
+                    d.day(),
+                    t.iter().map(|t| t.duration().num_minutes()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(totals, vec![(1, vec![60]), (2, vec![60, 60])]);
+
+        // Parts after the end of the range are removed
+        let days = group_by_day(&tasks, from, from + Duration::days(1));
+        assert_eq!(days.len(), 1);
+    }
+
+    #[test]
+    fn test_group_by_day_keeps_ids() {
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 23, 0, 0).unwrap();
+        let tasks = [
+            Task::new("a".to_owned(), start, start + Duration::hours(2), None).with_id(Some(1)),
+            Task::new(
+                "b".to_owned(),
+                start + Duration::hours(3),
+                start + Duration::hours(4),
+                None,
+            )
+            .with_id(Some(2)),
+        ];
+
+        let from = Utc.with_ymd_and_hms(2022, 7, 1, 0, 0, 0).unwrap();
+        let days = group_by_day(&tasks, from, from + Duration::days(7));
+        let ids: Vec<(u32, Vec<Option<i64>>)> = days
+            .iter()
+            .map(|(d, t)| (d.day(), t.iter().map(|t| t.id()).collect()))
+            .collect();
+        // Both parts of the task crossing midnight keep its id
+        assert_eq!(ids, vec![(1, vec![Some(1)]), (2, vec![Some(1), Some(2)])]);
+    }
+}
@@ -0,0 +1,143 @@
+//! Rounding of task durations for reporting, stored times are never rounded.
+use chrono::Duration;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Direction to round durations in.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoundingMode {
+    /// Round to the nearest increment, halfway values are rounded up
+    Nearest,
+    /// Round up to the next increment
+    Up,
+    /// Round down to the previous increment
+    Down,
+}
+
+/// Which durations rounding is applied to.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoundingScope {
+    /// Round each task's duration, totals are the sum of the rounded tasks
+    Task,
+    /// Leave each task's duration unrounded and only round totals
+    Total,
+}
+
+/// Rounding rule for durations, e.g. round up to 15 minutes per task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rounding {
+    pub mode: RoundingMode,
+    /// Size of the increment in minutes, 0 disables rounding.
+    pub minutes: u32,
+    pub scope: RoundingScope,
+}
+
+impl Default for Rounding {
+    fn default() -> Self {
+        Rounding {
+            mode: RoundingMode::Nearest,
+            minutes: 0,
+            scope: RoundingScope::Task,
+        }
+    }
+}
+
+impl Rounding {
+    /// Round `duration` to the increment, ignoring the scope.
+    pub fn round(&self, duration: Duration) -> Duration {
+        if self.minutes == 0 {
+            return duration;
+        }
+
+        let step = Duration::minutes(self.minutes as i64).num_milliseconds();
+        let milli = duration.num_milliseconds();
+        let down = milli.div_euclid(step) * step;
+        let remainder = milli - down;
+
+        let rounded = match self.mode {
+            RoundingMode::Down => down,
+            RoundingMode::Up if remainder == 0 => down,
+            RoundingMode::Up => down + step,
+            RoundingMode::Nearest if remainder * 2 >= step => down + step,
+            RoundingMode::Nearest => down,
+        };
+
+        Duration::milliseconds(rounded)
+    }
+
+    /// Duration to display for a single task.
+    pub fn task(&self, duration: Duration) -> Duration {
+        match self.scope {
+            RoundingScope::Task => self.round(duration),
+            RoundingScope::Total => duration,
+        }
+    }
+
+    /// Total of a group of task durations, e.g. for a day or project.
+    pub fn total<I: IntoIterator<Item = Duration>>(&self, durations: I) -> Duration {
+        let sum = durations
+            .into_iter()
+            .fold(Duration::zero(), |acc, d| acc + self.task(d));
+
+        match self.scope {
+            RoundingScope::Task => sum,
+            RoundingScope::Total => self.round(sum),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::{Rounding, RoundingMode, RoundingScope};
+
+    #[test]
+    fn test_round() {
+        let tests = [
+            (RoundingMode::Nearest, 15, 7, 0),
+            (RoundingMode::Nearest, 15, 8, 15),
+            (RoundingMode::Nearest, 6, 10, 12),
+            (RoundingMode::Up, 15, 1, 15),
+            (RoundingMode::Up, 15, 30, 30),
+            (RoundingMode::Down, 6, 11, 6),
+            (RoundingMode::Down, 0, 11, 11),
+        ];
+
+        for (mode, minutes, d, a) in tests {
+            let rounding = Rounding {
+                mode,
+                minutes,
+                scope: RoundingScope::Task,
+            };
+
+            assert_eq!(
+                rounding.round(Duration::minutes(d)),
+                Duration::minutes(a),
+                "testing: {:?} to {} min of {} min == {} min",
+                mode,
+                minutes,
+                d,
+                a
+            );
+        }
+    }
+
+    #[test]
+    fn test_total_scope() {
+        let durations = [Duration::minutes(5), Duration::minutes(5)];
+        let mut rounding = Rounding {
+            mode: RoundingMode::Up,
+            minutes: 15,
+            scope: RoundingScope::Task,
+        };
+        assert_eq!(rounding.total(durations), Duration::minutes(30));
+
+        rounding.scope = RoundingScope::Total;
+        assert_eq!(rounding.task(durations[0]), Duration::minutes(5));
+        assert_eq!(rounding.total(durations), Duration::minutes(15));
+    }
+}
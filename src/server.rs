@@ -0,0 +1,443 @@
+//! Local HTTP server exposing tasks, projects and reports as a JSON API.
+//!
+//! Endpoints:
+//! - `GET /current`, `POST /current` to start a task, `DELETE /current` to end it
+//!   (`?discard=true` to not save it)
+//! - `GET /tasks` (`?from=&to=&match=&project=&tag=&search=&sort=&desc=&limit=&offset=`),
+//!   `POST /tasks`
+//! - `GET /tasks/{id}`, `PUT /tasks/{id}`, `DELETE /tasks/{id}`
+//! - `GET /projects`, `GET /projects/{name}`, `PUT /projects/{name}`, `DELETE /projects/{name}`
+//! - `GET /report` (`?from=&to=&match=&project=&tag=&search=`, this week by default)
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server as HttpServer};
+
+use crate::period::{self, parse_local_datetime, Period, PeriodKind};
+use crate::projects::ProjectPattern;
+use crate::query::{RangeMatch, SortKey, TaskQuery};
+use crate::timekeep::Timekeep;
+use crate::{Project, Task};
+
+/// Error response, with the HTTP status code to send.
+#[derive(Debug)]
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: &str) -> ApiError {
+        ApiError {
+            status: 400,
+            message: message.to_owned(),
+        }
+    }
+
+    fn not_found(message: &str) -> ApiError {
+        ApiError {
+            status: 404,
+            message: message.to_owned(),
+        }
+    }
+}
+
+/// Errors from the database or file system are internal errors, others from
+/// timekeep are from invalid requests, e.g. a task with invalid times or a
+/// change cancelled by a hook.
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        let internal = e
+            .chain()
+            .any(|c| c.is::<rusqlite::Error>() || c.is::<io::Error>());
+        ApiError {
+            status: if internal { 500 } else { 400 },
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(e: serde_json::Error) -> Self {
+        ApiError {
+            status: 400,
+            message: format!("invalid JSON: {}", e),
+        }
+    }
+}
+
+type ApiResult = Result<(u16, Value), ApiError>;
+
+/// Body of a request to start a task.
+#[derive(Debug, Deserialize)]
+struct StartRequest {
+    project_name: String,
+    start_time: Option<DateTime<Utc>>,
+    description: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Replace the current task instead of ending it.
+    #[serde(default)]
+    overwrite: bool,
+}
+
+/// Report row for a single project.
+#[derive(Debug, Serialize)]
+struct ProjectTotal {
+    project: String,
+    client: Option<String>,
+    currency: Option<String>,
+    seconds: i64,
+    amount: Option<f64>,
+}
+
+/// Report row for a level of the project hierarchy.
+#[derive(Debug, Serialize)]
+struct RollUpTotal {
+    project: String,
+    depth: usize,
+    seconds: i64,
+    amounts: BTreeMap<String, f64>,
+}
+
+/// HTTP server handling requests one at a time.
+pub struct Server {
+    http: HttpServer,
+}
+
+impl Server {
+    /// Listen on `address`, e.g. `127.0.0.1:8765`, port 0 picks any free port.
+    pub fn bind(address: &str) -> anyhow::Result<Server> {
+        let http =
+            HttpServer::http(address).map_err(|e| anyhow!("can't listen on {}: {}", address, e))?;
+        Ok(Server { http })
+    }
+
+    /// Address the server is listening on.
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Respond to requests until the server is stopped.
+    pub fn serve(&self, timekeep: &Timekeep) {
+        let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+
+        for mut request in self.http.incoming_requests() {
+            let (status, body) = match handle(&mut request, timekeep) {
+                Ok(response) => response,
+                Err(e) => (e.status, json!({ "error": e.message })),
+            };
+            let response = Response::from_string(body.to_string())
+                .with_status_code(status)
+                .with_header(content_type.clone());
+            if let Err(e) = request.respond(response) {
+                eprintln!("Error: failed to respond to request: {}", e);
+            }
+        }
+    }
+
+    /// Stop serving, from another thread.
+    pub fn stop(&self) {
+        self.http.unblock();
+    }
+}
+
+/// Decode percent encoded `text`, with `+` as a space if `plus_space`.
+fn decode(text: &str, plus_space: bool) -> Result<String, ApiError> {
+    let invalid = || ApiError {
+        status: 400,
+        message: format!("invalid percent encoding: {}", text),
+    };
+
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        match b {
+            b'%' => {
+                let hex = rest.get(..2).ok_or_else(invalid)?;
+                let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+                rest = &rest[2..];
+            }
+            b'+' if plus_space => bytes.push(b' '),
+            b => bytes.push(b),
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+/// Query string parameters, later values replace earlier ones.
+fn parse_query(query: &str) -> Result<BTreeMap<String, String>, ApiError> {
+    query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (key, value) = p.split_once('=').unwrap_or((p, ""));
+            Ok((decode(key, true)?, decode(value, true)?))
+        })
+        .collect()
+}
+
+/// Value of parameter `name`, parsed by `parse`.
+fn parse_param<T>(
+    query: &BTreeMap<String, String>,
+    name: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Option<T>, ApiError> {
+    match query.get(name) {
+        Some(value) => match parse(value) {
+            Some(v) => Ok(Some(v)),
+            None => Err(ApiError::bad_request(&format!(
+                "invalid {} parameter: {}",
+                name, value
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Tasks selected by the `from`, `to`, `match`, `project`, `tag` and `search`
+/// parameters, with `default` times for those not given. The order and number
+/// of tasks are given by `sort`, `desc`, `limit` and `offset`.
+fn task_query(
+    query: &BTreeMap<String, String>,
+    default: (DateTime<Utc>, DateTime<Utc>),
+) -> Result<TaskQuery, ApiError> {
+    let from = parse_param(query, "from", |f| parse_local_datetime(f).ok())?;
+    let to = parse_param(query, "to", |t| parse_local_datetime(t).ok())?;
+    let (from, to) = (from.unwrap_or(default.0), to.unwrap_or(default.1));
+
+    let mut tasks = TaskQuery::new()
+        .range(from, to)
+        .projects(query.get("project").map(|p| ProjectPattern::new(p)))
+        .descending(query.get("desc").map(|d| d == "true").unwrap_or(false));
+    if let Some(range_match) = parse_param(query, "match", |m| RangeMatch::from_str(m, true).ok())?
+    {
+        tasks = tasks.range_match(range_match);
+    }
+    if let Some(tag) = query.get("tag") {
+        tasks = tasks.tag(tag);
+    }
+    if let Some(text) = query.get("search") {
+        tasks = tasks.search(text);
+    }
+    if let Some(sort) = parse_param(query, "sort", |s| SortKey::from_str(s, true).ok())? {
+        tasks = tasks.sort_by(sort);
+    }
+    if let Some(limit) = parse_param(query, "limit", |l| l.parse().ok())? {
+        tasks = tasks.limit(limit);
+    }
+    if let Some(offset) = parse_param(query, "offset", |o| o.parse().ok())? {
+        tasks = tasks.offset(offset);
+    }
+
+    Ok(tasks)
+}
+
+/// Id of an existing task.
+fn task_id(timekeep: &Timekeep, text: &str) -> Result<i64, ApiError> {
+    let not_found = || ApiError::not_found(&format!("no task with id {}", text));
+    let id = text.parse().map_err(|_| not_found())?;
+    match timekeep.task(id)? {
+        Some(_) => Ok(id),
+        None => Err(not_found()),
+    }
+}
+
+fn handle(request: &mut Request, timekeep: &Timekeep) -> ApiResult {
+    let url = request.url().to_owned();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let query = parse_query(query)?;
+    let segments = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| decode(s, false))
+        .collect::<Result<Vec<String>, ApiError>>()?;
+    let segments: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
+
+    let mut body = String::new();
+    request
+        .as_reader()
+        .read_to_string(&mut body)
+        .map_err(|e| ApiError::bad_request(&format!("failed to read request: {}", e)))?;
+
+    match (request.method(), segments.as_slice()) {
+        (Method::Get, ["current"]) => Ok((200, json!({ "current": timekeep.current()? }))),
+        (Method::Post, ["current"]) => {
+            let start: StartRequest = serde_json::from_str(&body)?;
+            let started = timekeep.start(
+                &start.project_name,
+                start.start_time,
+                start.description.as_deref(),
+                &start.tags,
+                start.overwrite,
+            )?;
+            Ok((201, serde_json::to_value(started)?))
+        }
+        (Method::Delete, ["current"]) => {
+            let discard = query.get("discard").map(|d| d == "true").unwrap_or(false);
+            let ended = timekeep.end(None, discard)?;
+            Ok((200, json!({ "ended": ended })))
+        }
+        (Method::Get, ["tasks"]) => {
+            let tasks = timekeep.query(&task_query(&query, period::all_time())?)?;
+            Ok((200, json!({ "tasks": tasks })))
+        }
+        (Method::Post, ["tasks"]) => {
+            let task: Task = serde_json::from_str(&body)?;
+            let task = timekeep.add(task.with_id(None))?;
+            Ok((201, json!({ "task": task })))
+        }
+        (Method::Get, ["tasks", id]) => {
+            let task = timekeep.task(task_id(timekeep, id)?)?;
+            Ok((200, json!({ "task": task })))
+        }
+        (Method::Put, ["tasks", id]) => {
+            let id = task_id(timekeep, id)?;
+            let task: Task = serde_json::from_str(&body)?;
+            Ok((200, json!({ "task": timekeep.edit(id, task)? })))
+        }
+        (Method::Delete, ["tasks", id]) => {
+            let id = task_id(timekeep, id)?;
+            Ok((200, json!({ "task": timekeep.delete(id)? })))
+        }
+        (Method::Get, ["projects"]) => Ok((200, json!({ "projects": timekeep.projects()? }))),
+        // Project names can contain slashes, e.g. `client/project`
+        (Method::Get, ["projects", name @ ..]) => {
+            let name = name.join("/");
+            match timekeep.project(&name)? {
+                Some(project) => Ok((200, json!({ "project": project }))),
+                None => Err(ApiError::not_found(&format!("no project named {}", name))),
+            }
+        }
+        (Method::Put, ["projects", name @ ..]) => {
+            let name = name.join("/");
+            // Name in the path takes precedence over any in the body
+            let mut details: Value = serde_json::from_str(&body)?;
+            match details.as_object_mut() {
+                Some(object) => object.insert("name".to_owned(), Value::String(name)),
+                None => return Err(ApiError::bad_request("project must be a JSON object")),
+            };
+            let project: Project = serde_json::from_value(details)?;
+            timekeep.save_project(&project)?;
+            Ok((200, json!({ "project": project })))
+        }
+        (Method::Delete, ["projects", name @ ..]) => {
+            let name = name.join("/");
+            if timekeep.project(&name)?.is_none() {
+                return Err(ApiError::not_found(&format!("no project named {}", name)));
+            }
+            timekeep.delete_project(&name)?;
+            Ok((200, json!({ "deleted": name })))
+        }
+        (Method::Get, ["report"]) => report(timekeep, &query),
+        _ => Err(ApiError::not_found(&format!(
+            "no endpoint for {} {}",
+            request.method(),
+            path
+        ))),
+    }
+}
+
+/// Time and amount billed for each project, for this week by default.
+fn report(timekeep: &Timekeep, query: &BTreeMap<String, String>) -> ApiResult {
+    let tasks = task_query(query, Period::current(PeriodKind::Week).range())?;
+    let report = timekeep.report(&tasks, &timekeep.config().rounding)?;
+
+    let totals: Vec<ProjectTotal> = report
+        .summaries
+        .iter()
+        .map(|s| ProjectTotal {
+            project: s.project.clone(),
+            client: s.client.clone(),
+            currency: s.currency.clone(),
+            seconds: s.duration.num_seconds(),
+            amount: s.amount(),
+        })
+        .collect();
+    let roll_up: Vec<RollUpTotal> = report
+        .roll_up()
+        .into_iter()
+        .map(|r| RollUpTotal {
+            project: r.project,
+            depth: r.depth,
+            seconds: r.duration.num_seconds(),
+            amounts: r.amounts,
+        })
+        .collect();
+
+    Ok((
+        200,
+        json!({
+            "from": report.from,
+            "to": report.to,
+            "projects": totals,
+            "roll_up": roll_up,
+            "total_seconds": report.total().num_seconds(),
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use anyhow::anyhow;
+
+    use super::{decode, parse_query, ApiError};
+
+    #[test]
+    fn test_parse_query() {
+        let cases = [
+            ("", vec![]),
+            ("from=2022-07-01", vec![("from", "2022-07-01")]),
+            (
+                "project=acme%2F**&to=2022-07-01+09%3A00",
+                vec![("project", "acme/**"), ("to", "2022-07-01 09:00")],
+            ),
+            ("flag&a=1&a=2", vec![("a", "2"), ("flag", "")]),
+        ];
+        for (query, expected) in cases {
+            let parsed = parse_query(query).unwrap();
+            let parsed: Vec<(&str, &str)> = parsed
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect();
+            assert_eq!(parsed, expected, "testing: parse_query({:?})", query);
+        }
+
+        assert_eq!(decode("a+b%20c", false).unwrap(), "a+b c");
+        assert!(decode("%zz", false).is_err());
+        assert!(decode("%2", false).is_err());
+    }
+
+    #[test]
+    fn test_api_error() {
+        let io_error = io::Error::new(io::ErrorKind::PermissionDenied, "read only");
+        let cases = [
+            (anyhow!("task cannot have end time before start time"), 400),
+            (anyhow::Error::from(rusqlite::Error::InvalidQuery), 500),
+            (
+                anyhow::Error::from(io_error).context("can't open database"),
+                500,
+            ),
+        ];
+        for (error, expected) in cases {
+            let message = error.to_string();
+            assert_eq!(
+                ApiError::from(error).status,
+                expected,
+                "testing: {}",
+                message
+            );
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Statistics about tracked time, see `database::task_statistics`.
+use chrono::{Duration, NaiveDate, Weekday};
+
+use crate::tasks::Task;
+
+/// Run of consecutive days with tracked time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Streak {
+    pub first: NaiveDate,
+    pub last: NaiveDate,
+}
+
+impl Streak {
+    /// Number of days in the streak.
+    pub fn days(&self) -> i64 {
+        (self.last - self.first).num_days() + 1
+    }
+}
+
+/// Statistics for the tasks in a period.
+///
+/// Time is attributed to the hour, weekday and day (UTC) it was tracked in, so
+/// a task running over midnight counts towards both days.
+#[derive(Debug)]
+pub struct Statistics {
+    pub task_count: usize,
+    pub total: Duration,
+    pub average: Duration,
+    pub longest: Option<Task>,
+    /// Time tracked on each weekday, starting from Monday.
+    pub by_weekday: Vec<(Weekday, Duration)>,
+    /// Time tracked in each hour of the day, indexed by hour.
+    pub by_hour: Vec<Duration>,
+    /// Time tracked on each day with any tracked time, in date order.
+    pub by_day: Vec<(NaiveDate, Duration)>,
+    /// Number of times the project changed between consecutive tasks, for
+    /// each day any tasks started on.
+    pub switches: Vec<(NaiveDate, u32)>,
+    /// Runs of consecutive tracked days, in date order.
+    pub streaks: Vec<Streak>,
+}
+
+impl Statistics {
+    /// Day with the most tracked time, the earliest if there's a tie.
+    pub fn busiest_day(&self) -> Option<(NaiveDate, Duration)> {
+        self.by_day
+            .iter()
+            .copied()
+            .reduce(|busiest, d| if d.1 > busiest.1 { d } else { busiest })
+    }
+
+    /// Average number of context switches per day with tasks.
+    pub fn average_switches(&self) -> f64 {
+        if self.switches.is_empty() {
+            return 0.0;
+        }
+        let total: u32 = self.switches.iter().map(|(_, n)| n).sum();
+        f64::from(total) / self.switches.len() as f64
+    }
+
+    /// Day with the most context switches, the earliest if there's a tie.
+    pub fn most_switches(&self) -> Option<(NaiveDate, u32)> {
+        self.switches
+            .iter()
+            .copied()
+            .reduce(|most, s| if s.1 > most.1 { s } else { most })
+    }
+
+    /// Longest streak of tracked days, the earliest if there's a tie.
+    pub fn longest_streak(&self) -> Option<Streak> {
+        self.streaks.iter().copied().reduce(|longest, s| {
+            if s.days() > longest.days() {
+                s
+            } else {
+                longest
+            }
+        })
+    }
+
+    /// Streak which is still going on `today`, i.e. it includes today or
+    /// yesterday as today may not have been tracked yet.
+    pub fn current_streak(&self, today: NaiveDate) -> Option<Streak> {
+        self.streaks
+            .last()
+            .copied()
+            .filter(|s| s.last >= today - Duration::days(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, Weekday};
+
+    use super::{Statistics, Streak};
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2022, 7, day).unwrap()
+    }
+
+    fn streak(first: u32, last: u32) -> Streak {
+        Streak {
+            first: date(first),
+            last: date(last),
+        }
+    }
+
+    #[test]
+    fn test_statistics() {
+        let stats = Statistics {
+            task_count: 4,
+            total: Duration::hours(8),
+            average: Duration::hours(2),
+            longest: None,
+            by_weekday: vec![(Weekday::Mon, Duration::hours(8))],
+            by_hour: vec![Duration::zero(); 24],
+            by_day: vec![
+                (date(1), Duration::hours(2)),
+                (date(2), Duration::hours(3)),
+                (date(5), Duration::hours(3)),
+            ],
+            switches: vec![(date(1), 1), (date(2), 4), (date(5), 4)],
+            streaks: vec![streak(1, 2), streak(5, 5)],
+        };
+
+        assert_eq!(stats.busiest_day(), Some((date(2), Duration::hours(3))));
+        assert_eq!(stats.average_switches(), 3.0);
+        assert_eq!(stats.most_switches(), Some((date(2), 4)));
+        assert_eq!(stats.longest_streak(), Some(streak(1, 2)));
+        assert_eq!(stats.longest_streak().unwrap().days(), 2);
+
+        let cases = [(5, true), (6, true), (7, false)];
+        for (today, current) in cases {
+            assert_eq!(
+                stats.current_streak(date(today)).is_some(),
+                current,
+                "testing: current streak on day {}",
+                today
+            );
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Short status line of the current task, for shell prompts and status bars.
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::duration::DurationFormat;
+use crate::tasks::CurrentTask;
+
+/// Format used when `--format` isn't given.
+pub const DEFAULT_FORMAT: &str = "{project} {elapsed}";
+
+/// Status of the current task for status bars, the `text`, `tooltip` and
+/// `class` fields are read by waybar's custom modules.
+#[derive(Debug, Serialize)]
+pub struct Status<'a> {
+    pub text: String,
+    pub tooltip: String,
+    pub class: &'static str,
+    pub project: &'a str,
+    pub description: Option<&'a str>,
+    pub tags: &'a [String],
+    pub start: String,
+    pub elapsed_seconds: i64,
+}
+
+impl<'a> Status<'a> {
+    pub fn new(
+        format: &str,
+        task: &'a CurrentTask,
+        now: DateTime<Utc>,
+        duration_format: DurationFormat,
+    ) -> Result<Status<'a>> {
+        Ok(Status {
+            text: format_status(format, task, now, duration_format)?,
+            tooltip: task.summary(duration_format),
+            class: "running",
+            project: task.project_name(),
+            description: task.description(),
+            tags: task.tags(),
+            start: task.start_time().to_rfc3339(),
+            elapsed_seconds: (now - *task.start_time()).num_seconds(),
+        })
+    }
+}
+
+/// Replace the `{project}`, `{elapsed}`, `{start}`, `{description}` and `{tags}`
+/// placeholders in `format` with details of `task`, `{{` and `}}` are literal braces.
+pub fn format_status(
+    format: &str,
+    task: &CurrentTask,
+    now: DateTime<Utc>,
+    duration_format: DurationFormat,
+) -> Result<String> {
+    let mut text = String::new();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let end = rest
+                    .find('}')
+                    .ok_or_else(|| anyhow!("unclosed placeholder in status format: {}", format))?;
+                let name = &rest[..end];
+                match name {
+                    "project" => text.push_str(task.project_name()),
+                    "elapsed" => text.push_str(&duration_format.format(now - *task.start_time())),
+                    "start" => {
+                        text.push_str(&task.start_time().naive_local().format("%R").to_string())
+                    }
+                    "description" => text.push_str(task.description().unwrap_or_default()),
+                    "tags" => text.push_str(&task.tags().join(", ")),
+                    _ => {
+                        return Err(anyhow!(
+                            "unknown placeholder in status format: {{{}}}",
+                            name
+                        ))
+                    }
+                }
+                chars = rest[end + 1..].chars();
+            }
+            c => text.push(c),
+        }
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::{format_status, Status, DEFAULT_FORMAT};
+    use crate::duration::DurationFormat;
+    use crate::tasks::CurrentTask;
+
+    #[test]
+    fn test_format_status() {
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 5, 0).unwrap();
+        let now = start + Duration::minutes(90);
+        let task = CurrentTask::new("acme/web".to_owned(), start, Some("fix login".to_owned()))
+            .with_tags(vec!["bug".to_owned(), "urgent".to_owned()]);
+
+        let cases = [
+            (DEFAULT_FORMAT, "acme/web 1 hr 30 min"),
+            ("{project} since {start}", "acme/web since 09:05"),
+            ("{description} [{tags}]", "fix login [bug, urgent]"),
+            ("{{{project}}}", "{acme/web}"),
+            ("no placeholders", "no placeholders"),
+        ];
+        for (format, expected) in cases {
+            assert_eq!(
+                format_status(format, &task, now, DurationFormat::Human).unwrap(),
+                expected,
+                "testing: format_status({:?})",
+                format
+            );
+        }
+        assert_eq!(
+            format_status("{elapsed}", &task, now, DurationFormat::Clock).unwrap(),
+            "01:30"
+        );
+
+        for format in ["{unknown}", "{project", "{}"] {
+            assert!(
+                format_status(format, &task, now, DurationFormat::Human).is_err(),
+                "testing: format_status({:?}) is an error",
+                format
+            );
+        }
+
+        let status = Status::new(DEFAULT_FORMAT, &task, now, DurationFormat::Compact).unwrap();
+        assert_eq!(status.text, "acme/web 1h30m");
+        assert_eq!(status.elapsed_seconds, 5400);
+        assert_eq!(status.class, "running");
+    }
+}
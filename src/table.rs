@@ -0,0 +1,327 @@
+//! Rendering of tables to fit the terminal, with optional colours.
+use std::env;
+use std::io::{self, IsTerminal};
+
+use terminal_size::{terminal_size, Width};
+
+/// ANSI colour codes used for projects, chosen so they're readable on both
+/// light and dark backgrounds.
+const PROJECT_COLOURS: [u8; 10] = [31, 32, 33, 34, 35, 36, 91, 92, 94, 95];
+/// Minimum width truncated columns are shrunk to.
+const MIN_TRUNCATED_WIDTH: usize = 10;
+const ELLIPSIS: char = '…';
+
+/// Output settings for rendering tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    /// Maximum line width, `None` for no limit.
+    pub width: Option<usize>,
+    /// Use ANSI colour codes.
+    pub colour: bool,
+}
+
+impl Style {
+    /// Style for writing to stdout, limited to the terminal width and using
+    /// colour only if stdout is a terminal and `NO_COLOR` isn't set.
+    ///
+    /// When stdout isn't a terminal the width is taken from `COLUMNS`, if set.
+    pub fn detect() -> Style {
+        let is_terminal = io::stdout().is_terminal();
+        let no_colour = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+
+        let width = match terminal_size() {
+            Some((Width(w), _)) if is_terminal => Some(w as usize),
+            _ => env::var("COLUMNS").ok().and_then(|c| c.parse().ok()),
+        };
+
+        Style {
+            width,
+            colour: is_terminal && !no_colour,
+        }
+    }
+
+    /// No width limit or colours.
+    pub fn plain() -> Style {
+        Style {
+            width: None,
+            colour: false,
+        }
+    }
+
+    /// Wrap `text` in the ANSI escape codes for `code`, if colour is enabled.
+    pub fn paint(&self, text: &str, code: u8) -> String {
+        if self.colour {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_owned()
+        }
+    }
+}
+
+/// Index of `project`'s colour in the palette of `PROJECT_COLOURS.len()`
+/// colours, the same project always gets the same index.
+pub fn project_colour_index(project: &str) -> usize {
+    // FNV-1a hash, std's hasher isn't guaranteed to be stable between releases
+    let hash = project.bytes().fold(0xcbf29ce484222325_u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    (hash % PROJECT_COLOURS.len() as u64) as usize
+}
+
+/// Colour code for `project`, the same project always gets the same colour.
+pub fn project_colour(project: &str) -> u8 {
+    PROJECT_COLOURS[project_colour_index(project)]
+}
+
+/// Shorten `text` to at most `width` characters, ending with an ellipsis if shortened.
+pub fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_owned();
+    }
+    if width == 0 {
+        return String::new();
+    }
+
+    let mut short: String = text.chars().take(width - 1).collect();
+    short.push(ELLIPSIS);
+    short
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// Table column heading and layout.
+#[derive(Debug, Clone)]
+pub struct Column {
+    title: String,
+    align: Align,
+    truncate: bool,
+}
+
+impl Column {
+    pub fn new(title: &str) -> Column {
+        Column {
+            title: title.to_owned(),
+            align: Align::Left,
+            truncate: false,
+        }
+    }
+
+    /// Align column contents to the right, e.g. for numbers.
+    pub fn right(mut self) -> Column {
+        self.align = Align::Right;
+        self
+    }
+
+    /// Allow column to be truncated to fit the table in the width.
+    pub fn truncate(mut self) -> Column {
+        self.truncate = true;
+        self
+    }
+}
+
+/// Table cell text with an optional colour.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cell {
+    text: String,
+    colour: Option<u8>,
+}
+
+impl Cell {
+    pub fn new(text: &str) -> Cell {
+        Cell {
+            text: text.to_owned(),
+            colour: None,
+        }
+    }
+
+    /// Cell containing a project name, coloured with the project's colour.
+    pub fn project(name: &str) -> Cell {
+        Cell {
+            text: name.to_owned(),
+            colour: Some(project_colour(name)),
+        }
+    }
+
+    /// Indent text by `level` steps, e.g. for showing a hierarchy.
+    pub fn indent(&mut self, level: usize) {
+        self.text.insert_str(0, &"  ".repeat(level));
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Cell {
+        Cell { text, colour: None }
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Cell {
+        Cell::new(text)
+    }
+}
+
+/// Table of rows, rendered with each column padded to the same width.
+#[derive(Debug, Clone)]
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(columns: Vec<Column>) -> Table {
+        Table {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Add row of cells, missing cells are left empty and extra cells are ignored.
+    pub fn add_row(&mut self, row: Vec<Cell>) {
+        self.rows.push(row);
+    }
+
+    /// Width of each column which fits the table in `width`, if possible.
+    ///
+    /// Truncatable columns are shrunk, widest first, down to a minimum width.
+    fn column_widths(&self, width: Option<usize>) -> Vec<usize> {
+        let mut widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                self.rows
+                    .iter()
+                    .filter_map(|r| r.get(i))
+                    .map(|c| c.text.chars().count())
+                    .chain([c.title.chars().count()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let max_width = match width {
+            Some(w) => w,
+            None => return widths,
+        };
+
+        // Each column is preceded by "| " and followed by a space, except the last
+        let separators = (3 * widths.len()).saturating_sub(1);
+        let mut total: usize = widths.iter().sum::<usize>() + separators;
+        while total > max_width {
+            let widest = widths
+                .iter()
+                .enumerate()
+                .filter(|(i, w)| self.columns[*i].truncate && **w > MIN_TRUNCATED_WIDTH)
+                .max_by_key(|(_, w)| **w)
+                .map(|(i, _)| i);
+
+            match widest {
+                Some(i) => {
+                    widths[i] -= 1;
+                    total -= 1;
+                }
+                None => break,
+            }
+        }
+
+        widths
+    }
+
+    /// Render table with a header row, each line ends with a new line.
+    pub fn render(&self, style: &Style) -> String {
+        let widths = self.column_widths(style.width);
+        let mut out = String::new();
+
+        let header: Vec<Cell> = self.columns.iter().map(|c| Cell::new(&c.title)).collect();
+        for (r, row) in [&header].into_iter().chain(self.rows.iter()).enumerate() {
+            let mut line = Vec::new();
+            for (i, column) in self.columns.iter().enumerate() {
+                let cell = row.get(i);
+                let text = truncate(cell.map_or("", |c| c.text.as_str()), widths[i]);
+
+                // Last column isn't padded to avoid trailing whitespace
+                let padded = if i + 1 == self.columns.len() && column.align == Align::Left {
+                    text
+                } else {
+                    match column.align {
+                        Align::Left => format!("{: <1$}", text, widths[i]),
+                        Align::Right => format!("{: >1$}", text, widths[i]),
+                    }
+                };
+
+                let painted = match (r, cell.and_then(|c| c.colour)) {
+                    (0, _) => style.paint(&padded, 1),
+                    (_, Some(colour)) => style.paint(&padded, colour),
+                    (_, None) => padded,
+                };
+                line.push(format!("| {}", painted));
+            }
+
+            out.push_str(line.join(" ").trim_end());
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{project_colour, truncate, Cell, Column, Style, Table};
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("description", 6), "descr…");
+        assert_eq!(truncate("description", 0), "");
+    }
+
+    #[test]
+    fn test_project_colour() {
+        // Colours must stay the same between releases
+        let cases = [
+            ("acme", 92),
+            ("acme/web", 33),
+            ("internal", 94),
+            ("client/web", 92),
+            ("client/app", 91),
+        ];
+        for (project, expected) in cases {
+            assert_eq!(project_colour(project), expected, "testing: {}", project);
+        }
+
+        assert_ne!(project_colour("client/web"), project_colour("client/app"));
+    }
+
+    #[test]
+    fn test_render_fits_width() {
+        let mut table = Table::new(vec![
+            Column::new("Project"),
+            Column::new("Hours").right(),
+            Column::new("Description").truncate(),
+        ]);
+        table.add_row(vec![
+            Cell::project("client"),
+            "1.50".into(),
+            "a very long description which doesn't fit".into(),
+        ]);
+
+        let style = Style {
+            width: Some(40),
+            colour: false,
+        };
+        assert_eq!(
+            table.render(&style),
+            "| Project | Hours | Description\n| client  |  1.50 | a very long descrip…\n"
+        );
+
+        assert_eq!(
+            table.render(&Style::plain()).lines().nth(1).unwrap(),
+            "| client  |  1.50 | a very long description which doesn't fit"
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! Contracted working time targets and overtime balance.
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+
+/// Working time targets, either daily or weekly hours can be given and the
+/// other is derived from the number of working days.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Targets {
+    /// Hours to work on each working day.
+    pub daily_hours: Option<f64>,
+    /// Hours to work each week.
+    pub weekly_hours: Option<f64>,
+    /// Days of the week with a target, others have no target.
+    pub working_days: Vec<Weekday>,
+    /// Date to calculate the overtime balance from.
+    pub start_date: Option<NaiveDate>,
+}
+
+impl Default for Targets {
+    fn default() -> Self {
+        Targets {
+            daily_hours: None,
+            weekly_hours: None,
+            working_days: vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ],
+            start_date: None,
+        }
+    }
+}
+
+/// Convert decimal number of hours to a duration, rounded to the nearest second.
+fn hours_duration(hours: f64) -> Duration {
+    Duration::seconds((hours * 3600.0).round() as i64)
+}
+
+impl Targets {
+    /// Hours to work on a single working day.
+    fn working_day_hours(&self) -> Result<f64> {
+        match (self.daily_hours, self.weekly_hours) {
+            (Some(d), _) => Ok(d),
+            (None, Some(_)) if self.working_days.is_empty() => {
+                Err(anyhow!("weekly target needs at least one working day"))
+            }
+            (None, Some(w)) => Ok(w / self.working_days.len() as f64),
+            (None, None) => Err(anyhow!("no daily or weekly target configured")),
+        }
+    }
+
+    /// Target for `day`, zero if it isn't a working day.
+    pub fn daily_target(&self, day: NaiveDate) -> Result<Duration> {
+        if !self.working_days.contains(&day.weekday()) {
+            return Ok(Duration::zero());
+        }
+
+        Ok(hours_duration(self.working_day_hours()?))
+    }
+
+    /// Target for a full week.
+    pub fn weekly_target(&self) -> Result<Duration> {
+        match self.weekly_hours {
+            Some(w) => Ok(hours_duration(w)),
+            None => Ok(hours_duration(
+                self.working_day_hours()? * self.working_days.len() as f64,
+            )),
+        }
+    }
+
+    /// Total target for all days from `from` up to, but excluding, `to`.
+    pub fn target_between(&self, from: NaiveDate, to: NaiveDate) -> Result<Duration> {
+        let mut total = Duration::zero();
+        for day in from.iter_days().take_while(|d| *d < to) {
+            total += self.daily_target(day)?;
+        }
+
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, Weekday};
+
+    use super::Targets;
+
+    #[test]
+    fn test_weekly_targets() {
+        let targets = Targets {
+            weekly_hours: Some(37.5),
+            ..Default::default()
+        };
+        // 2022-07-01 is a Friday
+        let friday = NaiveDate::from_ymd_opt(2022, 7, 1).unwrap();
+
+        assert_eq!(
+            targets.daily_target(friday).unwrap(),
+            Duration::minutes(450)
+        );
+        assert_eq!(
+            targets.daily_target(friday.succ_opt().unwrap()).unwrap(),
+            Duration::zero()
+        );
+        assert_eq!(
+            targets.weekly_target().unwrap(),
+            Duration::minutes(37 * 60 + 30)
+        );
+        assert_eq!(
+            targets
+                .target_between(friday, friday + Duration::days(7))
+                .unwrap(),
+            Duration::minutes(37 * 60 + 30)
+        );
+    }
+
+    #[test]
+    fn test_daily_targets() {
+        let targets = Targets {
+            daily_hours: Some(6.0),
+            working_days: vec![Weekday::Mon, Weekday::Tue],
+            ..Default::default()
+        };
+
+        assert_eq!(targets.weekly_target().unwrap(), Duration::hours(12));
+        assert!(Targets::default().weekly_target().is_err());
+    }
+}
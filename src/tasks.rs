@@ -1,48 +1,15 @@
 //! Task and CurrentTask types and related functionality.
-use std::path::Path;
-use std::{fmt, fs};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Result};
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::database;
-
-/// Divides two integers and rounds result towards nearest integer.
-///
-/// Values ending in .5 are rounded up to the larger integer.
-fn rounded_div(numerator: i64, denominator: i64) -> i64 {
-    (numerator + (denominator / 2)) / denominator
-}
-
-/// Format duration as a human readable string.
-fn human_duration(d: Duration) -> String {
-    let milli = d.num_milliseconds();
-    if milli < 1000 {
-        return format!("{} ms", milli);
-    };
-
-    let mut seconds = rounded_div(milli, 1000);
-    if seconds < 60 {
-        return format!("{} s", seconds);
-    };
-
-    let mut minutes = seconds / 60;
-    seconds -= minutes * 60;
-    if minutes < 60 {
-        return format!("{} min {} s", minutes, seconds);
-    };
-
-    let mut hours = minutes / 60;
-    minutes = minutes - (hours * 60) + rounded_div(seconds, 60);
-    if hours < 24 {
-        return format!("{} hr {} min", hours, minutes);
-    };
-
-    let days = hours / 24;
-    hours = hours - days * 24 + rounded_div(minutes, 60);
-    format!("{} day {} hr", days, hours)
-}
+use crate::duration::DurationFormat;
+use crate::hooks::{Action, Hooks};
 
 /// Task which started at a certain time but is still ongoing.
 ///
@@ -52,6 +19,8 @@ pub struct CurrentTask {
     project_name: String,
     start_time: DateTime<Utc>,
     description: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
 }
 
 impl CurrentTask {
@@ -64,6 +33,7 @@ impl CurrentTask {
             project_name,
             start_time,
             description,
+            tags: Vec::new(),
         }
     }
 
@@ -72,37 +42,35 @@ impl CurrentTask {
             project_name,
             start_time: Utc::now(),
             description,
+            tags: Vec::new(),
         }
     }
 
+    /// Set the task's tags, replacing any existing ones.
+    pub fn with_tags(mut self, tags: Vec<String>) -> CurrentTask {
+        self.tags = tags;
+        self
+    }
+
     pub fn end_with_time(self, time: DateTime<Utc>) -> Result<Task> {
         if time < self.start_time {
             return Err(anyhow!(
                 "task cannot have end time ({}) before start time ({})",
-                time.naive_local().format("%R %v").to_string(),
-                self.start_time.naive_local().format("%R %v").to_string()
+                time.naive_local().format("%R %v"),
+                self.start_time.naive_local().format("%R %v")
             ));
         }
 
-        Ok(Task::new(
-            self.project_name,
-            self.start_time,
-            time,
-            self.description,
-        ))
+        Ok(
+            Task::new(self.project_name, self.start_time, time, self.description)
+                .with_tags(self.tags),
+        )
     }
 
     pub fn end(self) -> Task {
         Task::from(self)
     }
 
-    pub fn save(self, file: &Path) -> Result<CurrentTask> {
-        let json = serde_json::to_string(&self)?;
-
-        fs::write(file, json)?;
-        Ok(self)
-    }
-
     pub fn load(file: &Path) -> Result<CurrentTask> {
         let json = fs::read_to_string(file)?;
 
@@ -113,33 +81,68 @@ impl CurrentTask {
     pub fn duration(&self) -> Duration {
         Utc::now() - self.start_time
     }
+
+    pub fn project_name(&self) -> &str {
+        &self.project_name
+    }
+
+    pub fn start_time(&self) -> &DateTime<Utc> {
+        &self.start_time
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Single line summary of the task, with the duration so far in `format`.
+    pub fn summary(&self, format: DurationFormat) -> String {
+        format!(
+            "{} since {} ({}){}",
+            self.project_name,
+            self.start_time.naive_local().format("%R %v"),
+            format.format(self.duration()),
+            details(&self.tags, self.description())
+        )
+    }
+}
+
+/// Tags, in brackets, and description to add after the main task details.
+fn details(tags: &[String], description: Option<&str>) -> String {
+    let mut text = String::new();
+    if !tags.is_empty() {
+        text.push_str(&format!(" [{}]", tags.join(", ")));
+    }
+    if let Some(d) = description {
+        text.push_str(&format!(": {}", d));
+    }
+
+    text
 }
 
 impl fmt::Display for CurrentTask {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "| {: <17} | {: <15} | {: <25} | {:0.50}",
-            self.start_time.naive_local().format("%R %v").to_string(),
-            human_duration(self.duration()),
-            self.project_name,
-            match &self.description {
-                Some(d) => d,
-                None => "",
-            }
-        )
+        write!(f, "{}", self.summary(DurationFormat::Human))
     }
 }
 
 /// Task which started at a certain time and has already finished.
 ///
 /// Contains an optional description for more details.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Task {
+    /// Database row id, `None` for tasks which haven't been stored yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<i64>,
     project_name: String,
     start_time: DateTime<Utc>,
     end_time: DateTime<Utc>,
     description: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
 }
 
 impl Task {
@@ -150,13 +153,31 @@ impl Task {
         description: Option<String>,
     ) -> Task {
         Task {
+            id: None,
             project_name,
             start_time,
             end_time,
             description,
+            tags: Vec::new(),
         }
     }
 
+    /// Set the task's tags, replacing any existing ones.
+    pub fn with_tags(mut self, tags: Vec<String>) -> Task {
+        self.tags = tags;
+        self
+    }
+
+    /// Set the database row id the task is stored in.
+    pub fn with_id(mut self, id: Option<i64>) -> Task {
+        self.id = id;
+        self
+    }
+
+    pub fn id(&self) -> Option<i64> {
+        self.id
+    }
+
     pub fn duration(&self) -> Duration {
         self.end_time - self.start_time
     }
@@ -176,132 +197,335 @@ impl Task {
     pub fn description(&self) -> Option<&str> {
         self.description.as_deref()
     }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Single line summary of the task, with the duration in `format`.
+    pub fn summary(&self, format: DurationFormat) -> String {
+        format!(
+            "{} from {} to {} ({}){}",
+            self.project_name,
+            self.start_time.naive_local().format("%R %v"),
+            self.end_time.naive_local().format("%R %v"),
+            format.format(self.duration()),
+            details(&self.tags, self.description())
+        )
+    }
+
+    /// Split task into separate tasks for each day it covers, split at midnight (UTC).
+    pub fn split_days(&self) -> Vec<Task> {
+        let mut parts = Vec::new();
+        let mut start = self.start_time;
+
+        while let Some(midnight) = start
+            .date_naive()
+            .succ_opt()
+            .map(|d| Utc.from_utc_datetime(&d.and_time(NaiveTime::MIN)))
+            .filter(|m| *m < self.end_time)
+        {
+            parts.push(self.with_times(start, midnight));
+            start = midnight;
+        }
+        parts.push(self.with_times(start, self.end_time));
+
+        parts
+    }
+
+    /// Copy of the task with different start and end times.
+    fn with_times(&self, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> Task {
+        Task {
+            start_time,
+            end_time,
+            ..self.clone()
+        }
+    }
 }
 
 impl From<CurrentTask> for Task {
     /// Uses current time as the activity end time when converting.
     fn from(activity: CurrentTask) -> Task {
         Task {
+            id: None,
             project_name: activity.project_name,
             start_time: activity.start_time,
             end_time: Utc::now(),
             description: activity.description,
+            tags: activity.tags,
         }
     }
 }
 
 impl fmt::Display for Task {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "| {: <17} | {: <17} | {: <15} | {: <25} | {:0.50}",
-            self.start_time.naive_local().format("%R %v").to_string(),
-            self.end_time.naive_local().format("%R %v").to_string(),
-            human_duration(self.duration()),
-            self.project_name,
-            match &self.description {
-                Some(d) => d,
-                None => "",
-            }
-        )
+        write!(f, "{}", self.summary(DurationFormat::Human))
     }
 }
 
+/// Path next to `file` with an additional `extension` appended to its name.
+fn sibling_path(file: &Path, extension: &str) -> PathBuf {
+    let mut name = file.file_name().unwrap_or_default().to_owned();
+    name.push(".");
+    name.push(extension);
+    file.with_file_name(name)
+}
+
+/// Take an exclusive lock for reading / writing the current task `file`.
+///
+/// Locks a separate `.lock` file next to the current task file, because the
+/// current task file itself is replaced when saving. The lock is held until
+/// the returned file is dropped.
+fn lock_current_file(file: &Path) -> Result<File> {
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sibling_path(file, "lock"))?;
+    lock.lock()?;
+
+    Ok(lock)
+}
+
+/// Result of starting a task.
+#[derive(Debug, Serialize)]
+pub struct Started {
+    /// Previously running task which was ended.
+    pub ended: Option<Task>,
+    pub started: CurrentTask,
+}
+
+/// Start a task, ending the current task now unless `overwrite` is set, in
+/// which case it's replaced. Runs the end and start hooks.
+///
+/// The current task is ended and the new one started in a single transaction.
 pub fn start_task(
     project_name: &str,
     start_time: Option<DateTime<Utc>>,
-    description: Option<&String>,
-    current_file: &Path,
-) -> Result<CurrentTask> {
-    let description: Option<String> = match description {
-        Some(d) => Some(d.to_string()),
-        None => None,
-    };
+    description: Option<&str>,
+    tags: &[String],
+    overwrite: bool,
+    database_file: &Path,
+    hooks: &Hooks,
+) -> Result<Started> {
+    let description: Option<String> = description.map(|d| d.to_string());
 
     let task = match start_time {
         Some(st) => CurrentTask::new(project_name.to_string(), st, description),
         None => CurrentTask::start(project_name.to_string(), description),
     };
+    let task = task.with_tags(tags.to_vec());
+
+    let ended = match (overwrite, database::load_current_task(database_file)?) {
+        (false, Some(current)) => {
+            // A task started in the future is ended at its start time, rather
+            // than before it
+            let end_time = Utc::now().max(*current.start_time());
+            Some(current.end_with_time(end_time)?)
+        }
+        _ => None,
+    };
 
-    Ok(task.save(&current_file)?)
+    // The pre-start hook can cancel starting, so it's run before anything
+    // happens to the current task
+    hooks.pre(Action::Start, &task)?;
+    if let Some(ended) = &ended {
+        hooks.pre(Action::End, ended)?;
+    }
+    database::switch_current_task(database_file, ended.as_ref(), &task, overwrite)?;
+    if let Some(ended) = &ended {
+        hooks.post(Action::End, ended);
+    }
+    hooks.post(Action::Start, &task);
+
+    Ok(Started {
+        ended,
+        started: task,
+    })
 }
 
+/// End the current task, if there is one, running the end hooks.
+///
+/// The ended task is saved unless `discard` is true.
 pub fn end_current_task(
-    current_file: &Path,
     database_file: &Path,
     end_time: Option<DateTime<Utc>>,
     discard: bool,
+    hooks: &Hooks,
 ) -> Result<Option<Task>> {
-    if !current_file.exists() {
-        return Ok(None);
+    let task = match database::load_current_task(database_file)? {
+        Some(t) => t,
+        None => return Ok(None),
     };
 
-    let task = CurrentTask::load(&current_file)?;
-
     let task = match end_time {
         Some(t) => task.end_with_time(t)?,
         None => task.end(),
     };
 
-    if !discard {
-        database::append_task(&database_file, &task)?;
+    hooks.pre(Action::End, &task)?;
+    database::finish_current_task(database_file, &task, !discard)?;
+    hooks.post(Action::End, &task);
+    Ok(Some(task))
+}
+
+/// Store a finished task, running the add hooks, returns the task with its id.
+pub fn add_task(database_file: &Path, task: Task, hooks: &Hooks) -> Result<Task> {
+    hooks.pre(Action::Add, &task)?;
+    let id = database::append_task(database_file, &task)?;
+    let task = task.with_id(Some(id));
+    hooks.post(Action::Add, &task);
+    Ok(task)
+}
+
+/// Replace the stored task `id` by `task`, running the edit hooks with the
+/// updated task.
+pub fn edit_task(database_file: &Path, id: i64, task: Task, hooks: &Hooks) -> Result<Task> {
+    if task.end_time < task.start_time {
+        return Err(anyhow!(
+            "task cannot have end time ({}) before start time ({})",
+            task.end_time.naive_local().format("%R %v"),
+            task.start_time.naive_local().format("%R %v")
+        ));
     }
+    let task = task.with_id(Some(id));
+
+    hooks.pre(Action::Edit, &task)?;
+    database::update_task(database_file, id, &task)?;
+    hooks.post(Action::Edit, &task);
+    Ok(task)
+}
+
+/// Delete the stored task `id`, running the delete hooks, returns the deleted task.
+pub fn delete_task(database_file: &Path, id: i64, hooks: &Hooks) -> Result<Task> {
+    let task =
+        database::load_task(database_file, id)?.ok_or_else(|| anyhow!("no task with id {}", id))?;
+
+    hooks.pre(Action::Delete, &task)?;
+    database::delete_task(database_file, id)?;
+    hooks.post(Action::Delete, &task);
+    Ok(task)
+}
+
+/// Move a current task stored in the legacy JSON `current_file` into the database.
+///
+/// The JSON file is removed once the task is stored. If the database already
+/// has a current task, the legacy one is ended when that task started, or
+/// an error is returned if the legacy task started later.
+pub fn import_legacy_current_task(current_file: &Path, database_file: &Path) -> Result<()> {
+    if !current_file.exists() {
+        return Ok(());
+    };
+    // The lock file is left in place, removing it would let another process
+    // lock a new file while this one is still locked
+    let _lock = lock_current_file(current_file)?;
+    if !current_file.exists() {
+        // Imported by another process while waiting for the lock
+        return Ok(());
+    };
+
+    let task = CurrentTask::load(current_file)?;
+    match database::load_current_task(database_file)? {
+        None => database::save_current_task(database_file, &task, false)?,
+        Some(current) if task.start_time() <= current.start_time() => {
+            let task = task.end_with_time(*current.start_time())?;
+            database::append_task(database_file, &task)?;
+        }
+        Some(current) => {
+            return Err(anyhow!(
+                "can't import current task {} from {}, it started after the running task {}",
+                task,
+                current_file.display(),
+                current
+            ))
+        }
+    };
 
     fs::remove_file(current_file)?;
-    Ok(Some(task))
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use chrono::Duration;
+    use std::{env, fs};
 
-    use super::{human_duration, rounded_div};
+    use chrono::{Datelike, Duration, TimeZone, Utc};
 
-    #[test]
-    fn test_rounded_div() {
-        let tests = [
-            (3, 2, 2),   // 3 / 2 = 1.5    -> 2
-            (2, 3, 1),   // 2 / 3 = 0.66   -> 1
-            (1, 2, 1),   // 1 / 2 = 0.5    -> 1
-            (1, 3, 0),   // 1 / 3 = 0.33   -> 0
-            (-3, 2, -1), // -3 / 2 = -1.5  -> -1
-            (-2, -3, 1), // -2 / -3 = 0.66 -> 1
-            (1, -2, 0),  // 1 / -2 = -0.5  -> 0
-            (-1, 3, 0),  // -1 / 3 = 0.33  -> 0
-        ];
+    use super::{import_legacy_current_task, CurrentTask, Task};
+    use crate::database;
 
-        for (n, d, a) in tests {
-            assert_eq!(
-                rounded_div(n, d),
-                a,
-                "testing: rounded_div({}, {}) == {}",
-                n,
-                d,
-                a
-            );
-        }
+    #[test]
+    fn test_split_days() {
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 22, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2022, 7, 3, 1, 30, 0).unwrap();
+        let task = Task::new("test".to_owned(), start, end, None);
+
+        let parts: Vec<(u32, i64)> = task
+            .split_days()
+            .iter()
+            .map(|t| (t.start_time().day(), t.duration().num_minutes()))
+            .collect();
+        assert_eq!(parts, vec![(1, 120), (2, 24 * 60), (3, 90)]);
+
+        let task = Task::new("test".to_owned(), start, start + Duration::hours(2), None);
+        assert_eq!(task.split_days().len(), 1);
     }
 
     #[test]
-    fn test_human_duration() {
-        let durations = [
-            (Duration::milliseconds(947), "947 ms"),
-            (Duration::milliseconds(1947), "2 s"),
-            (Duration::seconds(57), "57 s"),
-            (Duration::seconds(157), "2 min 37 s"),
-            (Duration::seconds(4734), "1 hr 19 min"),
-            (Duration::seconds(92750), "1 day 2 hr"),
-            (Duration::hours(173), "7 day 5 hr"),
+    fn test_import_legacy_current_task() {
+        let folder = env::temp_dir().join(format!("timekeep-test-legacy-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let current_file = folder.join("current.json");
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap();
+
+        // (legacy start hour, running task's start hour, imported, stored legacy task's end hour)
+        let cases = [
+            (0, None, true, None),
+            (0, Some(2), true, Some(2)),
+            (3, Some(2), false, None),
         ];
-
-        for (d, a) in durations {
-            assert_eq!(
-                human_duration(d),
-                a,
-                "testing: human_duration({}) == {}",
-                d,
-                a
+        for (i, (legacy_hour, running_hour, imported, end_hour)) in cases.into_iter().enumerate() {
+            let database_file = folder.join(format!("{}.db", i));
+            let legacy = CurrentTask::new(
+                "legacy".to_owned(),
+                start + Duration::hours(legacy_hour),
+                None,
             );
+            fs::write(&current_file, serde_json::to_string(&legacy).unwrap()).unwrap();
+            if let Some(hour) = running_hour {
+                let running =
+                    CurrentTask::new("running".to_owned(), start + Duration::hours(hour), None);
+                database::save_current_task(&database_file, &running, false).unwrap();
+            }
+
+            let result = import_legacy_current_task(&current_file, &database_file);
+            assert_eq!(result.is_ok(), imported, "testing: case {}", i);
+            assert_eq!(current_file.exists(), !imported, "testing: case {}", i);
+
+            let current = database::load_current_task(&database_file)
+                .unwrap()
+                .unwrap();
+            let expected = if running_hour.is_some() {
+                "running"
+            } else {
+                "legacy"
+            };
+            assert_eq!(current.project_name(), expected, "testing: case {}", i);
+
+            let ended: Vec<_> = database::extract_all_tasks(&database_file)
+                .unwrap()
+                .iter()
+                .map(|t| *t.end_time())
+                .collect();
+            let expected: Vec<_> = end_hour
+                .map(|h| start + Duration::hours(h))
+                .into_iter()
+                .collect();
+            assert_eq!(ended, expected, "testing: case {}", i);
         }
+        let _ = fs::remove_file(&current_file);
+
+        fs::remove_dir_all(&folder).unwrap();
     }
 }
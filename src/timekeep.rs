@@ -0,0 +1,344 @@
+//! Library interface for tracking time, used by the command line and HTTP
+//! server, and by other programs embedding timekeep.
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+
+use crate::backup::Backup;
+use crate::config::Config;
+use crate::importers::ImportedTask;
+use crate::projects::ProjectPattern;
+use crate::query::TaskQuery;
+use crate::report::Report;
+use crate::rounding::Rounding;
+use crate::stats::Statistics;
+use crate::tasks::Started;
+use crate::{database, tasks, CurrentTask, DataFiles, Project, Task};
+
+/// Timekeep's data and config, with methods for each operation which return
+/// the affected tasks and projects instead of printing them.
+///
+/// Hooks from the config are run for every change to the tasks.
+pub struct Timekeep {
+    files: DataFiles,
+    config: Config,
+}
+
+impl Timekeep {
+    /// Use the data in `files`, with the config stored there.
+    pub fn new(files: DataFiles) -> Result<Timekeep> {
+        let config = Config::load(files.config_file())?;
+        Timekeep::with_config(files, config)
+    }
+
+    /// Use the data in `files`, with `config` instead of the stored config.
+    pub fn with_config(files: DataFiles, config: Config) -> Result<Timekeep> {
+        tasks::import_legacy_current_task(files.current_file(), files.database_file())?;
+        Ok(Timekeep { files, config })
+    }
+
+    pub fn files(&self) -> &DataFiles {
+        &self.files
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Currently running task, if there is one.
+    pub fn current(&self) -> Result<Option<CurrentTask>> {
+        database::load_current_task(self.files.database_file())
+    }
+
+    /// Currently running task, read without creating or upgrading the
+    /// database, for frequent checks such as shell prompts.
+    pub fn peek_current(&self) -> Result<Option<CurrentTask>> {
+        database::peek_current_task(self.files.database_file())
+    }
+
+    /// Start a task now, or at `start_time`, ending the current task unless
+    /// `overwrite` is set, in which case the current task is replaced.
+    pub fn start(
+        &self,
+        project_name: &str,
+        start_time: Option<DateTime<Utc>>,
+        description: Option<&str>,
+        tags: &[String],
+        overwrite: bool,
+    ) -> Result<Started> {
+        tasks::start_task(
+            project_name,
+            start_time,
+            description,
+            tags,
+            overwrite,
+            self.files.database_file(),
+            &self.config.hooks,
+        )
+    }
+
+    /// End the current task now, or at `end_time`, returning it if there was
+    /// one. The task is only saved if `discard` is false.
+    pub fn end(&self, end_time: Option<DateTime<Utc>>, discard: bool) -> Result<Option<Task>> {
+        tasks::end_current_task(
+            self.files.database_file(),
+            end_time,
+            discard,
+            &self.config.hooks,
+        )
+    }
+
+    /// Store a finished task, returning it with its id.
+    pub fn add(&self, task: Task) -> Result<Task> {
+        tasks::add_task(self.files.database_file(), task, &self.config.hooks)
+    }
+
+    /// Stored task with `id`, if there is one.
+    pub fn task(&self, id: i64) -> Result<Option<Task>> {
+        database::load_task(self.files.database_file(), id)
+    }
+
+    /// Most recently finished task.
+    pub fn last_task(&self) -> Result<Option<Task>> {
+        database::last_task(self.files.database_file())
+    }
+
+    /// Replace the stored task `id` by `task`, returning the updated task.
+    pub fn edit(&self, id: i64, task: Task) -> Result<Task> {
+        if self.task(id)?.is_none() {
+            return Err(anyhow!("no task with id {}", id));
+        }
+        tasks::edit_task(self.files.database_file(), id, task, &self.config.hooks)
+    }
+
+    /// Delete the stored task `id`, returning the deleted task.
+    pub fn delete(&self, id: i64) -> Result<Task> {
+        tasks::delete_task(self.files.database_file(), id, &self.config.hooks)
+    }
+
+    /// Stored tasks selected by `query`.
+    pub fn query(&self, query: &TaskQuery) -> Result<Vec<Task>> {
+        database::extract_tasks(self.files.database_file(), query)
+    }
+
+    /// Time and amount billed for each project in the tasks selected by
+    /// `query`, with durations rounded by `rounding`.
+    pub fn report(&self, query: &TaskQuery, rounding: &Rounding) -> Result<Report> {
+        let tasks = self.query(query)?;
+        let projects = self.projects()?;
+        let (from, to) = query.bounds();
+        Ok(Report::new(from, to, tasks, &projects, rounding))
+    }
+
+    /// Statistics about the tasks started from `from` until `to`, only for
+    /// projects matching `project` if given.
+    pub fn statistics(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        project: Option<&ProjectPattern>,
+    ) -> Result<Statistics> {
+        database::task_statistics(self.files.database_file(), from, to, project)
+    }
+
+    /// Store tasks read from an import file, skipping any already imported.
+    ///
+    /// Returns whether each task was new, nothing is stored if `dry_run` is true.
+    pub fn import(&self, tasks: &[ImportedTask], dry_run: bool) -> Result<Vec<bool>> {
+        database::import_tasks(self.files.database_file(), tasks, dry_run)
+    }
+
+    /// Backup of all tasks, projects and the current task.
+    pub fn backup(&self) -> Result<Backup> {
+        database::create_backup(self.files.database_file())
+    }
+
+    /// Store everything from `backup`, fails if there's any data already
+    /// unless `replace` is true in which case it's deleted first.
+    pub fn restore(&self, backup: &Backup, replace: bool) -> Result<()> {
+        database::restore_backup(self.files.database_file(), backup, replace)
+    }
+
+    /// All stored projects.
+    pub fn projects(&self) -> Result<Vec<Project>> {
+        database::extract_projects(self.files.database_file())
+    }
+
+    /// Stored details of project `name`, if there are any.
+    pub fn project(&self, name: &str) -> Result<Option<Project>> {
+        database::load_project(self.files.database_file(), name)
+    }
+
+    /// Store the details of `project`, replacing any with the same name.
+    pub fn save_project(&self, project: &Project) -> Result<()> {
+        database::save_project(self.files.database_file(), project)
+    }
+
+    /// Names of stored projects and projects used by tasks, most recently used first.
+    pub fn recent_projects(&self) -> Result<Vec<String>> {
+        database::recent_projects(self.files.database_file())
+    }
+
+    /// Delete the stored details of project `name`, its tasks are kept.
+    pub fn delete_project(&self, name: &str) -> Result<()> {
+        database::delete_project(self.files.database_file(), name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::Timekeep;
+    use crate::config::Config;
+    use crate::hooks::Action;
+    use crate::projects::ProjectPattern;
+    use crate::query::TaskQuery;
+    use crate::{DataFiles, Task};
+
+    #[test]
+    fn test_timekeep() {
+        let folder = env::temp_dir().join(format!("timekeep-test-facade-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let files = DataFiles::new(&folder).unwrap();
+        let timekeep = Timekeep::with_config(files, Config::default()).unwrap();
+
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap();
+        let started = timekeep
+            .start("acme/web", Some(start), None, &[], false)
+            .unwrap();
+        assert!(started.ended.is_none());
+        let started = timekeep
+            .start("acme/api", Some(start), None, &[], true)
+            .unwrap();
+        assert!(started.ended.is_none(), "overwritten task isn't ended");
+        assert_eq!(
+            timekeep.current().unwrap().unwrap().project_name(),
+            "acme/api"
+        );
+
+        let ended = timekeep
+            .end(Some(start + Duration::hours(2)), false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(ended.duration(), Duration::hours(2));
+        assert!(timekeep.end(None, false).unwrap().is_none());
+
+        let task = Task::new(
+            "internal".to_owned(),
+            start + Duration::days(1),
+            start + Duration::days(1) + Duration::minutes(30),
+            None,
+        );
+        let added = timekeep.add(task).unwrap();
+        let id = added.id().unwrap();
+        assert_eq!(
+            timekeep.task(id).unwrap().unwrap().project_name(),
+            "internal"
+        );
+
+        let cases = [
+            (TaskQuery::new(), 2),
+            (TaskQuery::new().project(ProjectPattern::new("acme/**")), 1),
+            (TaskQuery::new().range(start, start + Duration::days(1)), 1),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(
+                timekeep.query(&query).unwrap().len(),
+                expected,
+                "testing: query({:?})",
+                query
+            );
+        }
+
+        let report = timekeep
+            .report(&TaskQuery::new(), &Config::default().rounding)
+            .unwrap();
+        assert_eq!(report.total(), Duration::minutes(150));
+        assert_eq!(report.summaries.len(), 2);
+
+        let stats = timekeep
+            .statistics(start, start + Duration::days(2), None)
+            .unwrap();
+        assert_eq!(stats.task_count, 2);
+        assert_eq!(
+            timekeep.recent_projects().unwrap(),
+            vec!["internal", "acme/api"]
+        );
+        assert!(timekeep.peek_current().unwrap().is_none());
+
+        assert!(timekeep.edit(id + 100, added.clone()).is_err());
+        timekeep.delete(id).unwrap();
+        assert!(timekeep.task(id).unwrap().is_none());
+
+        let backup = timekeep.backup().unwrap();
+        assert_eq!(backup.tasks.len(), 1);
+        assert!(timekeep.restore(&backup, false).is_err());
+        timekeep.restore(&backup, true).unwrap();
+        assert_eq!(timekeep.query(&TaskQuery::new()).unwrap().len(), 1);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_start_cancelled() {
+        let folder = env::temp_dir().join(format!("timekeep-test-cancel-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let ended = folder.join("ended");
+        let files = DataFiles::new(&folder).unwrap();
+        let mut config = Config::default();
+        config
+            .hooks
+            .pre
+            .insert(Action::Start, "! grep -q internal".to_owned());
+        config
+            .hooks
+            .pre
+            .insert(Action::End, format!("touch {}", ended.display()));
+        let timekeep = Timekeep::with_config(files, config).unwrap();
+
+        let start = Utc.with_ymd_and_hms(2022, 7, 1, 9, 0, 0).unwrap();
+        timekeep
+            .start("acme/web", Some(start), None, &[], false)
+            .unwrap();
+        let error = timekeep
+            .start("internal", None, None, &[], false)
+            .unwrap_err();
+        assert!(error.to_string().contains("pre-start hook"), "{}", error);
+
+        // The original task is still running, and wasn't ended
+        let current = timekeep.current().unwrap().unwrap();
+        assert_eq!(current.project_name(), "acme/web");
+        assert_eq!(current.start_time(), &start);
+        assert!(timekeep.query(&TaskQuery::new()).unwrap().is_empty());
+        assert!(!ended.exists(), "pre-end hook isn't run");
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_start_after_future_task() {
+        let folder = env::temp_dir().join(format!("timekeep-test-future-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let timekeep = Timekeep::new(DataFiles::new(&folder).unwrap()).unwrap();
+
+        let future = Utc::now() + Duration::hours(1);
+        timekeep
+            .start("acme/web", Some(future), None, &[], false)
+            .unwrap();
+        let started = timekeep.start("internal", None, None, &[], false).unwrap();
+
+        // The future task is ended at its start time
+        let ended = started.ended.unwrap();
+        assert_eq!(ended.project_name(), "acme/web");
+        assert_eq!(ended.start_time(), &future);
+        assert_eq!(ended.end_time(), &future);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
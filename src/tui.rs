@@ -0,0 +1,665 @@
+//! Interactive terminal interface for tracking and editing tasks.
+use std::time::Duration as StdDuration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    Block, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState,
+};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::fuzzy;
+use crate::period::{parse_local_datetime, Period, PeriodKind};
+use crate::query::TaskQuery;
+use crate::reminders::{Clock, SystemClock};
+use crate::table::project_colour;
+use crate::tasks::{CurrentTask, Task};
+use crate::timekeep::Timekeep;
+
+/// How long to wait for input before redrawing, so the current task's duration ticks.
+const TICK: StdDuration = StdDuration::from_secs(1);
+/// Format of times in the edit form.
+const EDIT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// Labels of the edit form fields, in the order they're shown.
+const EDIT_FIELDS: [&str; 5] = ["Project", "Start", "End", "Description", "Tags"];
+
+/// Key bindings shown in the status line when there's no message.
+const NORMAL_HELP: &str =
+    "s start  x stop  e edit  d delete  w day/week  r refresh  ↑/↓ select  q quit";
+const PICK_HELP: &str = "type to search  ↑/↓ select  enter start  esc cancel";
+const EDIT_HELP: &str = "tab/↑/↓ move between fields  enter save  esc cancel";
+
+/// What the keyboard currently controls.
+#[derive(Debug, PartialEq)]
+enum Mode {
+    Normal,
+    /// Choosing a project to start a task for, matching projects by `query`.
+    PickProject {
+        query: String,
+        selected: usize,
+    },
+    /// Editing task `id`, with the text of each of `EDIT_FIELDS`.
+    Edit {
+        id: i64,
+        fields: [String; 5],
+        focus: usize,
+    },
+    /// Waiting for confirmation before deleting task `id`.
+    ConfirmDelete {
+        id: i64,
+    },
+}
+
+struct App<'a> {
+    timekeep: &'a Timekeep,
+    /// Source of the current time, replaced in tests.
+    clock: Box<dyn Clock>,
+    /// Shows this week's tasks instead of today's.
+    week: bool,
+    tasks: Vec<Task>,
+    current: Option<CurrentTask>,
+    /// Known projects, most recently used first.
+    projects: Vec<String>,
+    table: TableState,
+    mode: Mode,
+    /// Result of the last action, shown instead of the key bindings.
+    message: Option<String>,
+    /// Redraw the whole screen, after hooks which may have printed over it.
+    redraw: bool,
+    quit: bool,
+}
+
+impl<'a> App<'a> {
+    fn new(timekeep: &'a Timekeep, clock: Box<dyn Clock>) -> Result<App<'a>> {
+        let mut app = App {
+            timekeep,
+            clock,
+            week: false,
+            tasks: Vec::new(),
+            current: None,
+            projects: Vec::new(),
+            table: TableState::default(),
+            mode: Mode::Normal,
+            message: None,
+            redraw: false,
+            quit: false,
+        };
+        app.reload()?;
+        // Start with the latest task selected
+        app.table.select(app.tasks.len().checked_sub(1));
+
+        Ok(app)
+    }
+
+    fn period(&self) -> Period {
+        let kind = if self.week {
+            PeriodKind::Week
+        } else {
+            PeriodKind::Day
+        };
+        Period::containing(kind, self.clock.now().date_naive())
+    }
+
+    /// Read tasks, the current task and projects from the database again.
+    fn reload(&mut self) -> Result<()> {
+        let (from, to) = self.period().range();
+        self.tasks = self.timekeep.query(&TaskQuery::new().range(from, to))?;
+        self.current = self.timekeep.current()?;
+        self.projects = self.timekeep.recent_projects()?;
+
+        let last = self.tasks.len().checked_sub(1);
+        let selected = match (self.table.selected(), last) {
+            (Some(s), Some(l)) => Some(s.min(l)),
+            (None, l) => l,
+            (_, None) => None,
+        };
+        self.table.select(selected);
+
+        Ok(())
+    }
+
+    fn selected_task(&self) -> Option<&Task> {
+        self.table.selected().and_then(|i| self.tasks.get(i))
+    }
+
+    /// Total of the listed tasks and the current task.
+    fn total(&self) -> Duration {
+        let now = self.clock.now();
+        let current = self.current.as_ref().map(|t| now - *t.start_time());
+        self.tasks
+            .iter()
+            .map(|t| t.duration())
+            .chain(current)
+            .fold(Duration::zero(), |a, b| a + b)
+    }
+
+    fn project_matches(&self, query: &str) -> Vec<&String> {
+        fuzzy::filter(query, &self.projects)
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Result<()> {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.quit = true;
+            return Ok(());
+        }
+
+        self.mode = match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Normal => self.normal_key(key)?,
+            Mode::PickProject { query, selected } => self.pick_project_key(key, query, selected)?,
+            Mode::Edit { id, fields, focus } => self.edit_key(key, id, fields, focus),
+            Mode::ConfirmDelete { id } => self.confirm_delete_key(key, id)?,
+        };
+
+        Ok(())
+    }
+
+    fn normal_key(&mut self, key: KeyEvent) -> Result<Mode> {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+            KeyCode::Char('w') => {
+                self.week = !self.week;
+                self.table.select(None);
+                self.reload()?;
+            }
+            KeyCode::Char('r') => self.reload()?,
+            KeyCode::Char('s') => {
+                return Ok(Mode::PickProject {
+                    query: String::new(),
+                    selected: 0,
+                })
+            }
+            KeyCode::Char('x') => {
+                self.redraw = true;
+                match self.timekeep.end(Some(self.clock.now()), false)? {
+                    Some(t) => {
+                        self.message = Some(format!(
+                            "Ended task: {}",
+                            t.summary(self.timekeep.config().duration_format)
+                        ))
+                    }
+                    None => self.message = Some("No current task".to_owned()),
+                }
+                self.reload()?;
+            }
+            KeyCode::Char('e') => {
+                if let Some(task) = self.selected_task() {
+                    return Ok(Mode::Edit {
+                        id: task_id(task)?,
+                        fields: edit_fields(task),
+                        focus: 0,
+                    });
+                }
+            }
+            KeyCode::Char('d') => {
+                if let Some(task) = self.selected_task() {
+                    return Ok(Mode::ConfirmDelete { id: task_id(task)? });
+                }
+            }
+            _ => {}
+        }
+
+        Ok(Mode::Normal)
+    }
+
+    fn pick_project_key(
+        &mut self,
+        key: KeyEvent,
+        mut query: String,
+        selected: usize,
+    ) -> Result<Mode> {
+        let count = self.project_matches(&query).len();
+        let selected = match key.code {
+            KeyCode::Esc => return Ok(Mode::Normal),
+            KeyCode::Enter => {
+                // Start the highlighted project, or a new one named by the query
+                let project = match self.project_matches(&query).get(selected) {
+                    Some(p) => p.to_string(),
+                    None => query.trim().to_owned(),
+                };
+                if project.is_empty() {
+                    return Ok(Mode::PickProject { query, selected });
+                }
+                self.start(&project)?;
+                return Ok(Mode::Normal);
+            }
+            KeyCode::Down => (selected + 1).min(count.saturating_sub(1)),
+            KeyCode::Up => selected.saturating_sub(1),
+            KeyCode::Backspace => {
+                query.pop();
+                0
+            }
+            KeyCode::Char(c) => {
+                query.push(c);
+                0
+            }
+            _ => selected,
+        };
+
+        Ok(Mode::PickProject { query, selected })
+    }
+
+    fn edit_key(&mut self, key: KeyEvent, id: i64, mut fields: [String; 5], focus: usize) -> Mode {
+        let focus = match key.code {
+            KeyCode::Esc => return Mode::Normal,
+            KeyCode::Enter => match self.save_edit(id, &fields) {
+                Ok(()) => return Mode::Normal,
+                Err(e) => {
+                    // Keep the form open so the mistake can be corrected
+                    self.message = Some(format!("Error: {}", e));
+                    focus
+                }
+            },
+            KeyCode::Tab | KeyCode::Down => (focus + 1) % EDIT_FIELDS.len(),
+            KeyCode::BackTab | KeyCode::Up => (focus + EDIT_FIELDS.len() - 1) % EDIT_FIELDS.len(),
+            KeyCode::Backspace => {
+                fields[focus].pop();
+                focus
+            }
+            KeyCode::Char(c) => {
+                fields[focus].push(c);
+                focus
+            }
+            _ => focus,
+        };
+
+        Mode::Edit { id, fields, focus }
+    }
+
+    fn confirm_delete_key(&mut self, key: KeyEvent, id: i64) -> Result<Mode> {
+        if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+            self.redraw = true;
+            self.timekeep.delete(id)?;
+            self.message = Some("Deleted task".to_owned());
+            self.reload()?;
+        } else {
+            self.message = Some("Delete cancelled".to_owned());
+        }
+
+        Ok(Mode::Normal)
+    }
+
+    /// End the current task and start a new one for `project`.
+    fn start(&mut self, project: &str) -> Result<()> {
+        self.redraw = true;
+        let started = self
+            .timekeep
+            .start(project, Some(self.clock.now()), None, &[], false)?;
+        self.message = Some(format!(
+            "Started task: {}",
+            started
+                .started
+                .summary(self.timekeep.config().duration_format)
+        ));
+
+        self.reload()
+    }
+
+    fn save_edit(&mut self, id: i64, fields: &[String; 5]) -> Result<()> {
+        let [project, start, end, description, tags] = fields;
+        let project = project.trim();
+        if project.is_empty() {
+            return Err(anyhow!("project can't be empty"));
+        }
+        let start = parse_local_datetime(start.trim())?;
+        let end = parse_local_datetime(end.trim())?;
+        let description = Some(description.trim())
+            .filter(|d| !d.is_empty())
+            .map(|d| d.to_owned());
+        let tags = tags
+            .split(',')
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .map(|t| t.to_owned())
+            .collect();
+
+        let task = Task::new(project.to_owned(), start, end, description).with_tags(tags);
+        self.redraw = true;
+        let task = self.timekeep.edit(id, task)?;
+        self.message = Some(format!(
+            "Updated task: {}",
+            task.summary(self.timekeep.config().duration_format)
+        ));
+
+        self.reload()
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        while !self.quit {
+            if self.redraw {
+                terminal.clear()?;
+                self.redraw = false;
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if !event::poll(TICK)? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.message = None;
+                    if let Err(e) = self.handle_key(key) {
+                        self.message = Some(format!("Error: {}", e));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let format = self.timekeep.config().duration_format;
+        let [current_area, list_area, status_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let current = match &self.current {
+            Some(t) => t.summary(format),
+            None => "No current task".to_owned(),
+        };
+        frame.render_widget(
+            Paragraph::new(current).block(Block::bordered().title(" Current task ")),
+            current_area,
+        );
+
+        let title = format!(
+            " {} — total {} ",
+            if self.week { "This week" } else { "Today" },
+            format.format(self.total())
+        );
+        let rows = self.tasks.iter().map(|t| {
+            Row::new(vec![
+                Span::raw(t.start_time().naive_local().format("%a %d %R").to_string()),
+                Span::raw(t.end_time().naive_local().format("%R").to_string()),
+                Span::raw(format.format(t.duration())),
+                Span::styled(t.project_name().to_owned(), project_style(t.project_name())),
+                Span::raw(t.tags().join(", ")),
+                Span::raw(t.description().unwrap_or_default().to_owned()),
+            ])
+        });
+        let widths = [
+            Constraint::Length(12),
+            Constraint::Length(5),
+            Constraint::Length(12),
+            Constraint::Percentage(20),
+            Constraint::Percentage(15),
+            Constraint::Fill(1),
+        ];
+        let header = ["Start", "End", "Duration", "Project", "Tags", "Description"];
+        let table = Table::new(rows, widths)
+            .header(Row::new(header).style(Style::new().add_modifier(Modifier::BOLD)))
+            .block(Block::bordered().title(title))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, list_area, &mut self.table);
+
+        let help = match self.mode {
+            Mode::Normal => NORMAL_HELP,
+            Mode::PickProject { .. } => PICK_HELP,
+            Mode::Edit { .. } => EDIT_HELP,
+            Mode::ConfirmDelete { .. } => "y delete  any other key cancel",
+        };
+        let status = self.message.as_deref().unwrap_or(help);
+        frame.render_widget(
+            Paragraph::new(status).style(Style::new().add_modifier(Modifier::DIM)),
+            status_area,
+        );
+
+        match &self.mode {
+            Mode::Normal => {}
+            Mode::PickProject { query, selected } => {
+                self.draw_project_picker(frame, query, *selected)
+            }
+            Mode::Edit { fields, focus, .. } => draw_edit_form(frame, fields, *focus),
+            Mode::ConfirmDelete { .. } => {
+                let area = popup_area(frame.area(), 40, 3);
+                frame.render_widget(Clear, area);
+                frame.render_widget(
+                    Paragraph::new("Delete the selected task? (y/n)")
+                        .block(Block::bordered().title(" Delete ")),
+                    area,
+                );
+            }
+        }
+    }
+
+    fn draw_project_picker(&self, frame: &mut Frame, query: &str, selected: usize) {
+        let area = popup_area(frame.area(), 50, 14);
+        frame.render_widget(Clear, area);
+        let block = Block::bordered().title(" Start task ");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let [input_area, list_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(inner);
+        frame.render_widget(Paragraph::new(format!("> {}", query)), input_area);
+        frame.set_cursor_position((
+            input_area.x + 2 + query.chars().count() as u16,
+            input_area.y,
+        ));
+
+        let matches = self.project_matches(query);
+        let items: Vec<ListItem> = if matches.is_empty() {
+            vec![ListItem::new(Span::styled(
+                format!("New project: {}", query.trim()),
+                Style::new().add_modifier(Modifier::ITALIC),
+            ))]
+        } else {
+            matches
+                .iter()
+                .map(|p| ListItem::new(Span::styled(p.as_str(), project_style(p))))
+                .collect()
+        };
+        let list = List::new(items).highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(selected));
+        frame.render_stateful_widget(list, list_area, &mut state);
+    }
+}
+
+fn draw_edit_form(frame: &mut Frame, fields: &[String; 5], focus: usize) {
+    let area = popup_area(frame.area(), 60, EDIT_FIELDS.len() as u16 + 2);
+    frame.render_widget(Clear, area);
+    let block = Block::bordered().title(" Edit task ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let label_width = EDIT_FIELDS.iter().map(|l| l.len()).max().unwrap_or(0) + 2;
+    let lines: Vec<Line> = EDIT_FIELDS
+        .iter()
+        .zip(fields)
+        .enumerate()
+        .map(|(i, (label, value))| {
+            let style = if i == focus {
+                Style::new().add_modifier(Modifier::BOLD)
+            } else {
+                Style::new()
+            };
+            Line::from(vec![
+                Span::styled(format!("{:<1$}", format!("{}:", label), label_width), style),
+                Span::raw(value.as_str()),
+            ])
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(lines), inner);
+    frame.set_cursor_position((
+        inner.x + (label_width + fields[focus].chars().count()) as u16,
+        inner.y + focus as u16,
+    ));
+}
+
+/// Area of at most `width` by `height` centred in `area`.
+fn popup_area(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// Same colour for `project` as in the tables printed by other commands.
+fn project_style(project: &str) -> Style {
+    let colour = match project_colour(project) {
+        c @ 30..=37 => Color::Indexed(c - 30),
+        c @ 90..=97 => Color::Indexed(c - 90 + 8),
+        _ => Color::Reset,
+    };
+    Style::new().fg(colour)
+}
+
+fn task_id(task: &Task) -> Result<i64> {
+    task.id()
+        .ok_or_else(|| anyhow!("task wasn't loaded from the database"))
+}
+
+/// Text of the edit form fields for `task`.
+fn edit_fields(task: &Task) -> [String; 5] {
+    [
+        task.project_name().to_owned(),
+        format_time(task.start_time()),
+        format_time(task.end_time()),
+        task.description().unwrap_or_default().to_owned(),
+        task.tags().join(", "),
+    ]
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.naive_local().format(EDIT_TIME_FORMAT).to_string()
+}
+
+/// Run the interactive interface until the user quits.
+pub fn run(timekeep: &Timekeep) -> Result<()> {
+    let mut app = App::new(timekeep, Box::new(SystemClock))?;
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use crossterm::event::{KeyCode, KeyEvent};
+
+    use super::{App, Mode};
+    use crate::config::Config;
+    use crate::reminders::Clock;
+    use crate::{DataFiles, Task, Timekeep};
+
+    struct FixedClock(DateTime<Utc>);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> DateTime<Utc> {
+            self.0
+        }
+    }
+
+    fn press(app: &mut App, keys: &[KeyCode]) {
+        for key in keys {
+            app.handle_key(KeyEvent::from(*key)).unwrap();
+        }
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        let keys: Vec<KeyCode> = text.chars().map(KeyCode::Char).collect();
+        press(app, &keys);
+    }
+
+    #[test]
+    fn test_keys() {
+        let folder = env::temp_dir().join(format!("timekeep-test-tui-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let files = DataFiles::new(&folder).unwrap();
+        let timekeep = Timekeep::with_config(files, Config::default()).unwrap();
+
+        let now = Utc.with_ymd_and_hms(2022, 7, 1, 12, 0, 0).unwrap();
+        let start = now - Duration::minutes(2);
+        let task = Task::new(
+            "acme/web".to_owned(),
+            start,
+            start + Duration::minutes(1),
+            None,
+        );
+        timekeep.add(task.clone()).unwrap();
+        timekeep
+            .start(
+                "internal",
+                Some(now - Duration::minutes(1)),
+                None,
+                &[],
+                false,
+            )
+            .unwrap();
+
+        let mut app = App::new(&timekeep, Box::new(FixedClock(now))).unwrap();
+        assert_eq!(app.tasks.len(), 1);
+        assert_eq!(app.table.selected(), Some(0));
+
+        // Picking a project by fuzzy search ends the current task
+        press(&mut app, &[KeyCode::Char('s')]);
+        type_text(&mut app, "web");
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(app.current.as_ref().unwrap().project_name(), "acme/web");
+        assert_eq!(app.tasks.len(), 2);
+
+        // Unmatched queries start a new project
+        press(&mut app, &[KeyCode::Char('s')]);
+        type_text(&mut app, "new");
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.current.as_ref().unwrap().project_name(), "new");
+
+        press(&mut app, &[KeyCode::Char('x')]);
+        assert!(app.current.is_none());
+        assert_eq!(app.tasks.len(), 4);
+
+        // Edit the project and description of the first task
+        press(&mut app, &[KeyCode::Char('k'), KeyCode::Char('e')]);
+        press(&mut app, &[KeyCode::Backspace; 3]);
+        type_text(&mut app, "api");
+        press(&mut app, &[KeyCode::Up, KeyCode::Up]);
+        type_text(&mut app, "docs");
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.mode, Mode::Normal, "{:?}", app.message);
+        assert_eq!(app.tasks[0].project_name(), "acme/api");
+        assert_eq!(app.tasks[0].description(), Some("docs"));
+        assert_eq!(
+            app.tasks[0].start_time().timestamp(),
+            task.start_time().timestamp()
+        );
+
+        // Invalid edits keep the form open
+        press(&mut app, &[KeyCode::Char('e'), KeyCode::Tab]);
+        type_text(&mut app, "x");
+        press(&mut app, &[KeyCode::Enter]);
+        assert!(matches!(app.mode, Mode::Edit { focus: 1, .. }));
+        press(&mut app, &[KeyCode::Esc]);
+
+        press(&mut app, &[KeyCode::Char('d'), KeyCode::Char('n')]);
+        assert_eq!(app.tasks.len(), 4);
+        press(&mut app, &[KeyCode::Char('d'), KeyCode::Char('y')]);
+        assert_eq!(app.tasks.len(), 3);
+        assert_eq!(app.tasks[0].project_name(), "internal");
+
+        press(&mut app, &[KeyCode::Char('q')]);
+        assert!(app.quit);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,255 @@
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+use serde_json::{json, Value};
+use timekeep::config::Config;
+use timekeep::server::Server;
+use timekeep::{DataFiles, Timekeep};
+
+/// Server running in a background thread on a fresh data folder.
+struct TestServer {
+    server: Arc<Server>,
+    address: SocketAddr,
+    folder: PathBuf,
+}
+
+impl TestServer {
+    fn start(name: &str) -> TestServer {
+        let folder = env::temp_dir().join(format!(
+            "timekeep-test-server-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+
+        let server = Arc::new(Server::bind("127.0.0.1:0").unwrap());
+        let address = server.address().unwrap();
+        let serving = Arc::clone(&server);
+        let base = folder.clone();
+        thread::spawn(move || {
+            let files = DataFiles::new(&base).unwrap();
+            let timekeep = Timekeep::with_config(files, Config::default()).unwrap();
+            serving.serve(&timekeep);
+        });
+
+        TestServer {
+            server,
+            address,
+            folder,
+        }
+    }
+
+    /// Send a request and return the response's status and JSON body.
+    fn request(&self, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+        let body = body.map(|b| b.to_string()).unwrap_or_default();
+        let mut stream = TcpStream::connect(self.address).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            self.address,
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.server.stop();
+        let _ = fs::remove_dir_all(&self.folder);
+    }
+}
+
+#[test]
+fn test_current_task() {
+    let server = TestServer::start("current");
+
+    let (status, body) = server.request("GET", "/current", None);
+    assert_eq!(status, 200);
+    assert_eq!(body, json!({ "current": null }));
+
+    let start = json!({
+        "project_name": "acme/web",
+        "start_time": "2022-07-01T09:00:00Z",
+        "description": "fix login",
+        "tags": ["bug"],
+    });
+    let (status, body) = server.request("POST", "/current", Some(start));
+    assert_eq!(status, 201);
+    assert_eq!(body["ended"], Value::Null);
+    assert_eq!(body["started"]["project_name"], "acme/web");
+
+    // Starting another task ends the current one, as `timekeep start` does
+    let start = json!({ "project_name": "internal", "start_time": "2022-07-01T10:00:00Z" });
+    let (status, body) = server.request("POST", "/current", Some(start));
+    assert_eq!(status, 201);
+    assert_eq!(body["ended"]["project_name"], "acme/web");
+    assert_eq!(body["ended"]["tags"], json!(["bug"]));
+
+    let (_, body) = server.request("GET", "/current", None);
+    assert_eq!(body["current"]["project_name"], "internal");
+
+    let (status, body) = server.request("DELETE", "/current?discard=true", None);
+    assert_eq!(status, 200);
+    assert_eq!(body["ended"]["project_name"], "internal");
+
+    let (_, body) = server.request("DELETE", "/current", None);
+    assert_eq!(body, json!({ "ended": null }));
+
+    // Only the first task was saved
+    let (_, body) = server.request("GET", "/tasks", None);
+    let tasks = body["tasks"].as_array().unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0]["description"], "fix login");
+}
+
+#[test]
+fn test_tasks() {
+    let server = TestServer::start("tasks");
+
+    let cases = [
+        ("acme/web", "2022-07-01T09:00:00Z", "2022-07-01T10:30:00Z"),
+        ("acme/api", "2022-07-02T09:00:00Z", "2022-07-02T10:00:00Z"),
+        ("internal", "2022-07-03T09:00:00Z", "2022-07-03T09:15:00Z"),
+    ];
+    let mut ids = Vec::new();
+    for (project, start, end) in cases {
+        let task = json!({ "project_name": project, "start_time": start, "end_time": end });
+        let (status, body) = server.request("POST", "/tasks", Some(task));
+        assert_eq!(status, 201, "testing: add {}", project);
+        ids.push(body["task"]["id"].as_i64().unwrap());
+    }
+
+    let cases = [
+        ("/tasks", 3),
+        ("/tasks?project=acme/**", 2),
+        ("/tasks?project=acme%2Fweb", 1),
+        ("/tasks?from=2022-07-02", 2),
+        ("/tasks?from=2022-07-01&to=2022-07-02+12:00", 2),
+        (
+            "/tasks?from=2022-07-02&to=2022-07-02+09:30&match=overlap",
+            1,
+        ),
+        ("/tasks?sort=duration&desc=true&limit=1", 1),
+        ("/tasks?offset=2", 1),
+    ];
+    for (path, expected) in cases {
+        let (status, body) = server.request("GET", path, None);
+        assert_eq!(status, 200, "testing: GET {}", path);
+        assert_eq!(
+            body["tasks"].as_array().unwrap().len(),
+            expected,
+            "testing: GET {}",
+            path
+        );
+    }
+
+    let (_, body) = server.request("GET", "/tasks?sort=duration&desc=true&limit=1", None);
+    assert_eq!(body["tasks"][0]["project_name"], "acme/web");
+
+    let path = format!("/tasks/{}", ids[0]);
+    let (status, body) = server.request("GET", &path, None);
+    assert_eq!(status, 200);
+    assert_eq!(body["task"]["project_name"], "acme/web");
+
+    let edited = json!({
+        "project_name": "acme/mobile",
+        "start_time": "2022-07-01T09:00:00Z",
+        "end_time": "2022-07-01T11:00:00Z",
+    });
+    let (status, body) = server.request("PUT", &path, Some(edited));
+    assert_eq!(status, 200);
+    assert_eq!(body["task"]["id"], ids[0]);
+    assert_eq!(body["task"]["project_name"], "acme/mobile");
+
+    let backwards = json!({
+        "project_name": "acme/mobile",
+        "start_time": "2022-07-01T11:00:00Z",
+        "end_time": "2022-07-01T09:00:00Z",
+    });
+    let (status, body) = server.request("PUT", &path, Some(backwards));
+    assert_eq!(status, 400);
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("before start time"));
+
+    let (status, _) = server.request("DELETE", &path, None);
+    assert_eq!(status, 200);
+
+    let cases = [
+        ("GET", path.as_str(), 404),
+        ("DELETE", path.as_str(), 404),
+        ("GET", "/tasks/abc", 404),
+        ("GET", "/unknown", 404),
+        ("POST", "/tasks", 400),
+        ("GET", "/tasks?sort=length", 400),
+        ("GET", "/tasks?limit=-1", 400),
+        ("GET", "/tasks?from=yesterday", 400),
+    ];
+    for (method, path, expected) in cases {
+        let (status, body) = server.request(method, path, None);
+        assert_eq!(status, expected, "testing: {} {}", method, path);
+        assert!(body["error"].is_string(), "testing: {} {}", method, path);
+    }
+}
+
+#[test]
+fn test_projects_and_report() {
+    let server = TestServer::start("projects");
+
+    let project = json!({ "client": "Acme Ltd", "rate": 50.0, "currency": "GBP" });
+    let (status, body) = server.request("PUT", "/projects/acme/web", Some(project));
+    assert_eq!(status, 200);
+    assert_eq!(body["project"]["name"], "acme/web");
+
+    let (_, body) = server.request("GET", "/projects/acme/web", None);
+    assert_eq!(body["project"]["client"], "Acme Ltd");
+    let (status, _) = server.request("GET", "/projects/acme", None);
+    assert_eq!(status, 404);
+
+    for (project, end) in [("acme/web", "10:30"), ("acme/api", "10:00")] {
+        let task = json!({
+            "project_name": project,
+            "start_time": "2022-07-01T09:00:00Z",
+            "end_time": format!("2022-07-01T{}:00Z", end),
+        });
+        let (status, _) = server.request("POST", "/tasks", Some(task));
+        assert_eq!(status, 201);
+    }
+
+    let (status, body) = server.request("GET", "/report?from=2022-07-01&to=2022-07-02", None);
+    assert_eq!(status, 200);
+    assert_eq!(body["total_seconds"], 9000);
+    let projects = body["projects"].as_array().unwrap();
+    assert_eq!(projects.len(), 2);
+    assert_eq!(projects[0]["project"], "acme/api");
+    assert_eq!(projects[0]["amount"], Value::Null);
+    assert_eq!(projects[1]["project"], "acme/web");
+    assert_eq!(projects[1]["amount"], 75.0);
+    assert_eq!(body["roll_up"][0]["project"], "acme");
+    assert_eq!(body["roll_up"][0]["seconds"], 9000);
+
+    let (status, body) = server.request("DELETE", "/projects/acme/web", None);
+    assert_eq!(status, 200);
+    assert_eq!(body["deleted"], "acme/web");
+    let (_, body) = server.request("GET", "/projects", None);
+    assert_eq!(body, json!({ "projects": [] }));
+    let (status, _) = server.request("DELETE", "/projects/acme/web", None);
+    assert_eq!(status, 404);
+}